@@ -0,0 +1,755 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::{
+    inst::{Instruction, Register},
+    symbols::{Symbol, SymbolTable},
+    DRVSim,
+};
+
+// ==== Type Definitions ===========================================================================
+
+// Assembled program image, starting at base.
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub base: u32,
+    pub data: Vec<u8>,
+    pub symbols: SymbolTable, // Labels, without size.
+}
+
+// Source line with labels and comments removed. Alignment and .org are resolved to padding
+// during the first pass.
+enum Stmt<'a> {
+    Inst { mnemonic: String, ops: Vec<&'a str> },
+    Data { width: u32, vals: Vec<&'a str> },
+    Bytes(Vec<u8>),
+    Space(u32),
+}
+
+// ==== Assembler ==================================================================================
+
+// Assemble a program for the DRV instruction subset, placed at base. Accepts the syntax produced
+// by the Instruction Display implementation, labels, the pseudo-instructions li, mv, j, ret and
+// nop, and the directives .byte, .half, .word, .ascii, .asciz, .space, .align, .balign and .org.
+pub fn assemble(text: &str, base: u32) -> Result<Assembly, anyhow::Error> {
+    // First pass: Assign addresses to labels and statements.
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut symbols = SymbolTable::new();
+    let mut stmts = vec![];
+    let mut adr = base;
+    for (line_idx, line) in text.lines().enumerate() {
+        let err = |e: anyhow::Error| anyhow!("Assembly line {}: {}", line_idx + 1, e);
+
+        let mut line = strip_comment(line).trim();
+        while let Some((name, rest)) = line.split_once(':') {
+            let name = name.trim();
+            if !is_ident(name) {
+                break;
+            }
+            if labels.insert(name.to_string(), adr).is_some() {
+                return Err(err(anyhow!("Duplicate label '{name}'.")));
+            }
+            symbols.insert(Symbol {
+                name: name.to_string(),
+                adr,
+                size: 0,
+            });
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let stmt = parse_stmt(line, base, adr).map_err(err)?;
+        let size = match &stmt {
+            Stmt::Inst { mnemonic, ops } if mnemonic == "li" && ops.len() == 2 => {
+                if li_is_short(ops[1]) {
+                    4
+                } else {
+                    8
+                }
+            }
+            Stmt::Inst { .. } => 4,
+            Stmt::Data { width, vals } => width * vals.len() as u32,
+            Stmt::Bytes(bytes) => bytes.len() as u32,
+            Stmt::Space(len) => *len,
+        };
+        stmts.push((line_idx, adr, stmt));
+        adr = adr
+            .checked_add(size)
+            .ok_or_else(|| err(anyhow!("Program exceeds the address space.")))?;
+    }
+
+    // Second pass: Emit statements.
+    let mut data = Vec::with_capacity((adr - base) as usize);
+    for (line_idx, adr, stmt) in stmts {
+        let err = |e: anyhow::Error| anyhow!("Assembly line {}: {}", line_idx + 1, e);
+        match stmt {
+            Stmt::Inst { mnemonic, ops } => {
+                for inst in assemble_inst(&mnemonic, &ops, adr, &labels).map_err(err)? {
+                    data.extend(inst.encode().map_err(err)?.to_le_bytes());
+                }
+            }
+            Stmt::Data { width, vals } => {
+                for val in vals {
+                    let val = parse_value(val, &labels).map_err(err)?;
+                    let bits = width * 8;
+                    let fits = bits == 32
+                        || val >> bits == 0
+                        || (-(1 << (bits - 1))..0).contains(&(val as i32));
+                    if !fits {
+                        return Err(err(anyhow!(
+                            "Value 0x{val:x} does not fit into {width} bytes."
+                        )));
+                    }
+                    data.extend(&val.to_le_bytes()[..width as usize]);
+                }
+            }
+            Stmt::Bytes(bytes) => data.extend(bytes),
+            Stmt::Space(len) => data.resize(data.len() + len as usize, 0),
+        }
+    }
+
+    Ok(Assembly {
+        base,
+        data,
+        symbols,
+    })
+}
+
+fn parse_stmt(line: &str, base: u32, adr: u32) -> Result<Stmt<'_>, anyhow::Error> {
+    let (mnemonic, rest) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(m, r)| (m, r.trim()));
+    let mnemonic = mnemonic.to_lowercase();
+
+    let stmt = match mnemonic.as_str() {
+        ".byte" => Stmt::Data {
+            width: 1,
+            vals: split_ops(rest)?,
+        },
+        ".half" => Stmt::Data {
+            width: 2,
+            vals: split_ops(rest)?,
+        },
+        ".word" => Stmt::Data {
+            width: 4,
+            vals: split_ops(rest)?,
+        },
+        ".ascii" => Stmt::Bytes(parse_string(rest)?),
+        ".asciz" => {
+            let mut bytes = parse_string(rest)?;
+            bytes.push(0);
+            Stmt::Bytes(bytes)
+        }
+        ".space" => Stmt::Space(parse_num(rest)?),
+        ".align" | ".balign" => {
+            let align = parse_num(rest)?;
+            let align = if mnemonic == ".align" {
+                if align > 16 {
+                    return Err(anyhow!("Invalid alignment of 2^{align} bytes."));
+                }
+                1 << align
+            } else {
+                if !align.is_power_of_two() || align > 1 << 16 {
+                    return Err(anyhow!("Invalid alignment of {align} bytes."));
+                }
+                align
+            };
+            Stmt::Space(adr.wrapping_neg() & (align - 1))
+        }
+        ".org" => {
+            // Offset from the start of the program:
+            let offset = parse_num(rest)?;
+            if offset < adr - base {
+                return Err(anyhow!(
+                    ".org 0x{offset:x} lies before the current offset 0x{:x}.",
+                    adr - base
+                ));
+            }
+            Stmt::Space(offset - (adr - base))
+        }
+        _ if mnemonic.starts_with('.') => return Err(anyhow!("Unknown directive '{mnemonic}'.")),
+        _ => Stmt::Inst {
+            mnemonic,
+            ops: split_ops(rest)?,
+        },
+    };
+    Ok(stmt)
+}
+
+fn assemble_inst(
+    mnemonic: &str,
+    ops: &[&str],
+    adr: u32,
+    labels: &HashMap<String, u32>,
+) -> Result<Vec<Instruction>, anyhow::Error> {
+    let op_count = match mnemonic {
+        "ecall" | "ebreak" | "dret" | "mret" | "nop" | "ret" => 0,
+        "fence" if ops.is_empty() => 0,
+        "j" => 1,
+        "lui" | "auipc" | "jal" | "jalr" | "lb" | "lh" | "lw" | "lbu" | "lhu" | "sb" | "sh"
+        | "sw" | "li" | "mv" => 2,
+        _ => 3,
+    };
+    if ops.len() != op_count {
+        return Err(anyhow!(
+            "'{mnemonic}' expects {op_count} operands, found {}.",
+            ops.len()
+        ));
+    }
+
+    let reg = |idx: usize| parse_reg(ops[idx]);
+    let num = |idx: usize| parse_num(ops[idx]);
+    let mem = |idx: usize| parse_mem(ops[idx]);
+    let target = |idx: usize| parse_target(ops[idx], adr, labels);
+    let upper = |idx: usize| {
+        let imm = parse_num(ops[idx])?;
+        if imm > 0xFFFFF {
+            return Err(anyhow!(
+                "Upper immediate 0x{imm:x} does not fit into 20 bits."
+            ));
+        }
+        Ok(imm << 12)
+    };
+
+    let inst = match mnemonic {
+        // RV32I:
+        "lui" => Instruction::LUI {
+            imm: upper(1)?,
+            rd: reg(0)?,
+        },
+        "auipc" => Instruction::AUIPC {
+            imm: upper(1)?,
+            rd: reg(0)?,
+        },
+        "jal" => Instruction::JAL {
+            imm: target(1)?,
+            rd: reg(0)?,
+        },
+        "jalr" => {
+            let (imm, rs1) = mem(1)?;
+            Instruction::JALR {
+                imm,
+                rs1,
+                rd: reg(0)?,
+            }
+        }
+        "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => {
+            let (imm, rs1, rs2) = (target(2)?, reg(0)?, reg(1)?);
+            match mnemonic {
+                "beq" => Instruction::BEQ { imm, rs2, rs1 },
+                "bne" => Instruction::BNE { imm, rs2, rs1 },
+                "blt" => Instruction::BLT { imm, rs2, rs1 },
+                "bge" => Instruction::BGE { imm, rs2, rs1 },
+                "bltu" => Instruction::BLTU { imm, rs2, rs1 },
+                _ => Instruction::BGEU { imm, rs2, rs1 },
+            }
+        }
+        "lb" | "lh" | "lw" | "lbu" | "lhu" => {
+            let ((imm, rs1), rd) = (mem(1)?, reg(0)?);
+            match mnemonic {
+                "lb" => Instruction::LB { imm, rs1, rd },
+                "lh" => Instruction::LH { imm, rs1, rd },
+                "lw" => Instruction::LW { imm, rs1, rd },
+                "lbu" => Instruction::LBU { imm, rs1, rd },
+                _ => Instruction::LHU { imm, rs1, rd },
+            }
+        }
+        "sb" | "sh" | "sw" => {
+            let ((imm, rs1), rs2) = (mem(1)?, reg(0)?);
+            match mnemonic {
+                "sb" => Instruction::SB { imm, rs2, rs1 },
+                "sh" => Instruction::SH { imm, rs2, rs1 },
+                _ => Instruction::SW { imm, rs2, rs1 },
+            }
+        }
+        "addi" | "slti" | "sltiu" | "xori" | "ori" | "andi" => {
+            let (imm, rs1, rd) = (num(2)?, reg(1)?, reg(0)?);
+            match mnemonic {
+                "addi" => Instruction::ADDI { imm, rs1, rd },
+                "slti" => Instruction::SLTI { imm, rs1, rd },
+                "sltiu" => Instruction::SLTIU { imm, rs1, rd },
+                "xori" => Instruction::XORI { imm, rs1, rd },
+                "ori" => Instruction::ORI { imm, rs1, rd },
+                _ => Instruction::ANDI { imm, rs1, rd },
+            }
+        }
+        "slli" | "srli" | "srai" => {
+            let (shamt, rs1, rd) = (num(2)?, reg(1)?, reg(0)?);
+            match mnemonic {
+                "slli" => Instruction::SLLI { shamt, rs1, rd },
+                "srli" => Instruction::SRLI { shamt, rs1, rd },
+                _ => Instruction::SRAI { shamt, rs1, rd },
+            }
+        }
+        "add" | "sub" | "sll" | "slt" | "sltu" | "xor" | "srl" | "sra" | "or" | "and" => {
+            let (rs2, rs1, rd) = (reg(2)?, reg(1)?, reg(0)?);
+            match mnemonic {
+                "add" => Instruction::ADD { rs2, rs1, rd },
+                "sub" => Instruction::SUB { rs2, rs1, rd },
+                "sll" => Instruction::SLL { rs2, rs1, rd },
+                "slt" => Instruction::SLT { rs2, rs1, rd },
+                "sltu" => Instruction::SLTU { rs2, rs1, rd },
+                "xor" => Instruction::XOR { rs2, rs1, rd },
+                "srl" => Instruction::SRL { rs2, rs1, rd },
+                "sra" => Instruction::SRA { rs2, rs1, rd },
+                "or" => Instruction::OR { rs2, rs1, rd },
+                _ => Instruction::AND { rs2, rs1, rd },
+            }
+        }
+        "fence" if ops.is_empty() => Instruction::FENCE {
+            fm: 0,
+            pred: 0xF,
+            succ: 0xF,
+        },
+        "fence" => Instruction::FENCE {
+            fm: parse_field(ops[0], "f")?,
+            pred: parse_field(ops[1], "p")?,
+            succ: parse_field(ops[2], "s")?,
+        },
+        "ecall" => Instruction::ECALL,
+        "ebreak" => Instruction::EBREAK,
+        "dret" => Instruction::DRET,
+        "mret" => Instruction::MRET,
+
+        // Zicsr:
+        "csrrw" | "csrrs" | "csrrc" => {
+            let (csr, rs1, rd) = (num(1)?, reg(2)?, reg(0)?);
+            match mnemonic {
+                "csrrw" => Instruction::CSRRW { csr, rs1, rd },
+                "csrrs" => Instruction::CSRRS { csr, rs1, rd },
+                _ => Instruction::CSRRC { csr, rs1, rd },
+            }
+        }
+        "csrrwi" | "csrrsi" | "csrrci" => {
+            let (csr, uimm, rd) = (num(1)?, num(2)?, reg(0)?);
+            match mnemonic {
+                "csrrwi" => Instruction::CSRRWI { csr, uimm, rd },
+                "csrrsi" => Instruction::CSRRSI { csr, uimm, rd },
+                _ => Instruction::CSRRCI { csr, uimm, rd },
+            }
+        }
+
+        // Pseudo-instructions:
+        "nop" => Instruction::ADDI {
+            imm: 0,
+            rs1: Register::X0,
+            rd: Register::X0,
+        },
+        "mv" => Instruction::ADDI {
+            imm: 0,
+            rs1: reg(1)?,
+            rd: reg(0)?,
+        },
+        "j" => Instruction::JAL {
+            imm: target(0)?,
+            rd: Register::X0,
+        },
+        "ret" => Instruction::JALR {
+            imm: 0,
+            rs1: Register::X1,
+            rd: Register::X0,
+        },
+        "li" => {
+            let (val, rd) = (parse_value(ops[1], labels)?, reg(0)?);
+            if li_is_short(ops[1]) {
+                return Ok(vec![Instruction::ADDI {
+                    imm: val,
+                    rs1: Register::X0,
+                    rd,
+                }]);
+            }
+            // The lower 12 bits are sign-extended by ADDI, which LUI compensates for:
+            let lo = (((val & 0xFFF) << 20) as i32 >> 20) as u32;
+            return Ok(vec![
+                Instruction::LUI {
+                    imm: val.wrapping_sub(lo) & 0xFFFFF000,
+                    rd,
+                },
+                Instruction::ADDI {
+                    imm: lo,
+                    rs1: rd,
+                    rd,
+                },
+            ]);
+        }
+
+        _ => return Err(anyhow!("Unknown instruction '{mnemonic}'.")),
+    };
+    Ok(vec![inst])
+}
+
+// li is a single ADDI if the value is a number that fits into a 12 bit immediate, and a
+// LUI/ADDI pair otherwise:
+fn li_is_short(op: &str) -> bool {
+    parse_num(op).is_ok_and(|val| (-0x800..0x800).contains(&(val as i32)))
+}
+
+// ==== Operand Parsing ============================================================================
+
+// Remove '#' and '//' comments outside of string literals:
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '#' || line[idx..].starts_with("//") {
+            return &line[..idx];
+        }
+    }
+    line
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && name != "."
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+fn split_ops(rest: &str) -> Result<Vec<&str>, anyhow::Error> {
+    if rest.is_empty() {
+        return Ok(vec![]);
+    }
+    let ops: Vec<&str> = rest.split(',').map(str::trim).collect();
+    if ops.iter().any(|op| op.is_empty()) {
+        return Err(anyhow!("Empty operand in '{rest}'."));
+    }
+    Ok(ops)
+}
+
+fn parse_reg(op: &str) -> Result<Register, anyhow::Error> {
+    let name = op.to_lowercase();
+    let idx = match name.as_str() {
+        "zero" => 0,
+        "ra" => 1,
+        "sp" => 2,
+        "gp" => 3,
+        "tp" => 4,
+        "t0" => 5,
+        "t1" => 6,
+        "t2" => 7,
+        "s0" | "fp" => 8,
+        "s1" => 9,
+        "a0" => 10,
+        "a1" => 11,
+        "a2" => 12,
+        "a3" => 13,
+        "a4" => 14,
+        "a5" => 15,
+        "xmpc" => Register::Xmpc as u32,
+        "xdpc" => Register::Xdpc as u32,
+        _ => name
+            .strip_prefix('x')
+            .and_then(|idx| idx.parse::<u32>().ok())
+            .filter(|idx| *idx < 16)
+            .ok_or_else(|| anyhow!("Invalid register '{op}'."))?,
+    };
+    Register::new(idx)
+}
+
+// Parse a decimal, hexadecimal (0x) or binary (0b) number. Negative numbers are returned as their
+// two's complement.
+fn parse_num(op: &str) -> Result<u32, anyhow::Error> {
+    let (negative, digits) = match op.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, op.strip_prefix('+').unwrap_or(op)),
+    };
+    let val = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        u32::from_str_radix(bin, 2)
+    } else {
+        digits.parse::<u32>()
+    }
+    .map_err(|_| anyhow!("Invalid number '{op}'."))?;
+
+    if negative {
+        if val > 0x80000000 {
+            return Err(anyhow!("Number '{op}' does not fit into 32 bits."));
+        }
+        Ok(val.wrapping_neg())
+    } else {
+        Ok(val)
+    }
+}
+
+// Number or label address:
+fn parse_value(op: &str, labels: &HashMap<String, u32>) -> Result<u32, anyhow::Error> {
+    if is_ident(op) {
+        return labels
+            .get(op)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown label '{op}'."));
+    }
+    parse_num(op)
+}
+
+// Jump or branch target, as a label or an offset relative to the instruction ('.+0x10'). Returns
+// the offset to the instruction.
+fn parse_target(op: &str, adr: u32, labels: &HashMap<String, u32>) -> Result<u32, anyhow::Error> {
+    match op.strip_prefix('.') {
+        Some("") => Ok(0),
+        Some(offset) if offset.starts_with(['+', '-']) => parse_num(offset),
+        _ => Ok(parse_value(op, labels)?.wrapping_sub(adr)),
+    }
+}
+
+// Memory operand, such as '0x10(X1)' or '(X1)':
+fn parse_mem(op: &str) -> Result<(u32, Register), anyhow::Error> {
+    let (imm, reg) = op
+        .strip_suffix(')')
+        .and_then(|op| op.split_once('('))
+        .ok_or_else(|| anyhow!("Invalid memory operand '{op}'."))?;
+    let imm = match imm.trim() {
+        "" => 0,
+        imm => parse_num(imm)?,
+    };
+    Ok((imm, parse_reg(reg.trim())?))
+}
+
+// Named FENCE field, such as 'p=15':
+fn parse_field(op: &str, name: &str) -> Result<u32, anyhow::Error> {
+    match op.split_once('=') {
+        Some((field, val)) if field.trim() == name => parse_num(val.trim()),
+        _ => Err(anyhow!("Expected FENCE field '{name}=', found '{op}'.")),
+    }
+}
+
+fn parse_string(op: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let content = op
+        .strip_prefix('"')
+        .and_then(|op| op.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("Invalid string literal '{op}'."))?;
+
+    let mut bytes = vec![];
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                _ => return Err(anyhow!("Invalid escape sequence in '{op}'.")),
+            },
+            '"' => return Err(anyhow!("Invalid string literal '{op}'.")),
+            c => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+    }
+    Ok(bytes)
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Assemble a program at base, and load it and its labels. Labels replace existing symbols of
+    // the same name.
+    pub fn load_asm(&mut self, text: &str, base: u32) -> Result<(), anyhow::Error> {
+        let assembly = assemble(text, base)?;
+        self.load_bin_bytes(&assembly.data, base)?;
+        self.symbols.merge(assembly.symbols);
+        Ok(())
+    }
+}
+
+// ==== Assembler Tests ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{asm::*, inst_decoding::decode_inst, run::HaltReason, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator() -> DRVSim {
+        DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        })
+    }
+
+    fn words(assembly: &Assembly) -> Vec<u32> {
+        assembly
+            .data
+            .chunks(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn asm_display_syntax() {
+        // Any decodable instruction assembles back from its textual representation:
+        let mut rng = StdRng::seed_from_u64(0xA53);
+        let mut checked = 0;
+        while checked < 5000 {
+            let word: u32 = rng.gen();
+            let Ok(inst) = decode_inst(word) else {
+                continue;
+            };
+            checked += 1;
+            let assembly = assemble(&inst.to_string(), ROM_START).unwrap();
+            assert_eq!(words(&assembly), vec![inst.encode().unwrap()], "{inst}");
+        }
+
+        let assembly = assemble("ADDI x1, zero, -1\nsw a5, (sp)\nfence", 0).unwrap();
+        assert_eq!(words(&assembly), vec![0xfff00093, 0x00f12023, 0x0ff0000f]);
+    }
+
+    #[test]
+    fn asm_labels_and_pseudo() {
+        let text = "
+            _start:
+                li X1, 10          # Short li
+                li X2, 0x12345800  // Long li
+                li X3, data
+            loop:   addi X1, X1, -1
+                bne X1, X0, loop
+                j .+8
+                nop
+                mv a0, X2
+                ret
+            data: .word data, loop
+        ";
+        let assembly = assemble(text, ROM_START).unwrap();
+        let expected = [
+            "addi X1, X0, 0xa",
+            "lui X2, 0x12346",
+            "addi X2, X2, 0xfffff800",
+            "lui X3, 0x1000",
+            "addi X3, X3, 0x2c",
+            "addi X1, X1, 0xffffffff",
+            "bne X1, X0, .+0xfffffffc",
+            "jal X0, .+0x8",
+            "addi X0, X0, 0x0",
+            "addi X10, X2, 0x0",
+            "jalr X0, 0x0(X1)",
+        ];
+        let mut expected: Vec<u32> = expected
+            .iter()
+            .map(|inst| words(&assemble(inst, 0).unwrap())[0])
+            .collect();
+        expected.extend([ROM_START + 0x2c, ROM_START + 0x14]);
+        assert_eq!(words(&assembly), expected);
+
+        assert_eq!(
+            assembly.symbols.by_name("loop").unwrap().adr,
+            ROM_START + 0x14
+        );
+        assert_eq!(assembly.symbols.by_name("_start").unwrap().adr, ROM_START);
+    }
+
+    #[test]
+    fn asm_data() {
+        let text = r#"
+            .byte 1, 0xff, -1
+            .align 2
+            .half 0x1234, -2
+            str: .asciz "a#\"\n"
+            .balign 8
+            .space 2
+            .org 0x14
+            .ascii "z"
+        "#;
+        let assembly = assemble(text, 0x100).unwrap();
+        assert_eq!(
+            assembly.data,
+            vec![
+                0x01, 0xff, 0xff, 0x00, 0x34, 0x12, 0xfe, 0xff, b'a', b'#', b'"', b'\n', 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, b'z'
+            ]
+        );
+        assert_eq!(assembly.symbols.by_name("str").unwrap().adr, 0x108);
+    }
+
+    #[test]
+    fn asm_errors() {
+        let invalid = [
+            "foo X1, X2",
+            "addi X1, X2",
+            "addi X1, X2, 0x800",
+            "addi X16, X2, 0",
+            "lui X1, 0x100000",
+            "beq X1, X2, nowhere",
+            "beq X1, X2, .+3",
+            "lw X1, 4(X2",
+            "a: nop\na: nop",
+            ".byte 0x100",
+            "nop\n.org 0x2",
+            ".ascii \"abc",
+            ".text",
+        ];
+        for text in invalid {
+            assert!(assemble(text, 0x100).is_err(), "{text}");
+        }
+
+        let err = assemble("nop\n\nadd X1, X2", 0).unwrap_err();
+        assert!(err.to_string().starts_with("Assembly line 3:"));
+    }
+
+    #[test]
+    fn asm_run() {
+        let mut sim = new_simulator();
+        let code = "
+            _start:
+                li a0, 0
+                li a1, 10
+            loop:
+                add a0, a0, a1
+                addi a1, a1, -1
+                bne a1, zero, loop
+                li a2, 0x2000100
+                sw a0, 0(a2)
+                ebreak
+        ";
+        sim.load_asm(code, ROM_START).unwrap();
+        sim.load_asm("result: .word 0", RAM_START + 0x100).unwrap();
+
+        match sim.run(1000) {
+            HaltReason::Ebreak(_) => (),
+            reason => panic!("{reason:?}"),
+        }
+        assert_eq!(sim.read_register(Register::X10).unwrap().val, 55);
+        assert_eq!(sim.read_w(RAM_START + 0x100).unwrap().val, 55);
+        assert_eq!(sim.symbols().by_name("loop").unwrap().adr, ROM_START + 0x8);
+        assert_eq!(
+            sim.symbols().by_name("result").unwrap().adr,
+            RAM_START + 0x100
+        );
+
+        // Reloading replaces the labels:
+        sim.load_asm(code, ROM_START).unwrap();
+        assert_eq!(sim.symbols().iter().filter(|s| s.name == "loop").count(), 1);
+    }
+}
@@ -0,0 +1,378 @@
+use std::{cell::RefCell, io::Write, path::PathBuf, process::ExitCode, rc::Rc};
+
+use anyhow::anyhow;
+use clap::Parser;
+use drv_isa_sim::{run::HaltReason, timer::Timer, uart::Uart, *};
+use serde::Deserialize;
+
+// Exit status if the simulation fails or the instruction limit is reached. Firmware exit statuses
+// are limited to 0..=MAX_EXIT_STATUS, so that they cannot be mistaken for a failure:
+const EXIT_SIM_FAILURE: u8 = 128;
+const MAX_EXIT_STATUS: u32 = 127;
+
+// ==== Command Line Interface =====================================================================
+
+/// Run RV32E/DRV firmware on the ISA simulator, printing an instruction trace.
+///
+/// Execution stops at an EBREAK or an instruction that jumps to itself. The exit status is then
+/// the value of a0 (x10), which must be between 0 and 127. If the simulation fails, the
+/// instruction limit is reached, or a0 is uninitialized or out of range, the exit status is 128.
+#[derive(Parser)]
+#[command(name = "drv-sim")]
+struct Args {
+    /// ELF file to load.
+    elf: PathBuf,
+
+    /// Memory map and simulator settings, as a TOML or (with a .json extension) JSON file.
+    #[arg(short, long)]
+    config: PathBuf,
+
+    /// Maximum number of instructions to execute.
+    #[arg(short = 'n', long, default_value_t = 1_000_000)]
+    max_steps: u64,
+
+    /// Do not print the instruction trace.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Annotate addresses in the trace with ELF symbols.
+    #[arg(short, long)]
+    symbolize: bool,
+}
+
+// ==== Config File ================================================================================
+
+// Simulator settings, mirroring DRVSimConfig. Numbers may be given as integers or as strings
+// such as "0x1000000".
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    entry: Option<Num>, // Defaults to the ELF entry point.
+    #[serde(default)]
+    mtvec: Num,
+    #[serde(default)]
+    dvec: Num,
+    #[serde(default)]
+    reg_init: InitConfig,
+    #[serde(default)]
+    fault_mode: FaultModeConfig,
+    #[serde(default)]
+    zicsr: bool,
+    mem_regions: Vec<RegionConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionConfig {
+    start: Num,
+    size: Num,
+    #[serde(rename = "type")]
+    region_type: RegionTypeConfig,
+    #[serde(default)]
+    init: InitConfig,
+    irq_line: Option<u32>, // Timer interrupt line.
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum RegionTypeConfig {
+    Ram,
+    Rom,
+    Uart,  // Transmitted bytes are written to stderr, keeping stdout for the trace.
+    Timer, // Machine timer, see timer.rs.
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum InitConfig {
+    Random,
+    Zero,
+    Ones,
+    #[default]
+    Error,
+    FixedByte(Num),
+    FixedWord(Num),
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FaultModeConfig {
+    #[default]
+    Error,
+    Trap,
+}
+
+type SharedUart = Rc<RefCell<Uart>>;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(try_from = "NumRepr")]
+struct Num(u32);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumRepr {
+    Int(u32),
+    Str(String),
+}
+
+impl TryFrom<NumRepr> for Num {
+    type Error = String;
+
+    fn try_from(repr: NumRepr) -> Result<Num, String> {
+        match repr {
+            NumRepr::Int(val) => Ok(Num(val)),
+            NumRepr::Str(text) => {
+                let digits = text.replace('_', "");
+                match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => digits.parse(),
+                }
+                .map(Num)
+                .map_err(|_| format!("invalid number '{text}'"))
+            }
+        }
+    }
+}
+
+impl InitConfig {
+    fn to_value_init(self) -> Result<ValueInit, anyhow::Error> {
+        Ok(match self {
+            InitConfig::Random => ValueInit::Random,
+            InitConfig::Zero => ValueInit::Zero,
+            InitConfig::Ones => ValueInit::Ones,
+            InitConfig::Error => ValueInit::Error,
+            InitConfig::FixedByte(Num(val)) => ValueInit::FixedByte(
+                u8::try_from(val).map_err(|_| anyhow!("fixed_byte 0x{val:x} exceeds a byte."))?,
+            ),
+            InitConfig::FixedWord(Num(val)) => ValueInit::FixedWord(val),
+        })
+    }
+}
+
+impl ConfigFile {
+    fn parse(text: &str, json: bool) -> Result<ConfigFile, anyhow::Error> {
+        if json {
+            Ok(serde_json::from_str(text)?)
+        } else {
+            Ok(toml::from_str(text)?)
+        }
+    }
+
+    // Also returns the UARTs, whose output is forwarded by the caller.
+    fn to_sim_config(&self) -> Result<(DRVSimConfig, Vec<SharedUart>), anyhow::Error> {
+        let mut mem_regions = vec![];
+        let mut uarts = vec![];
+        for region in &self.mem_regions {
+            let end = region.start.0.checked_add(region.size.0).ok_or(anyhow!(
+                "Memory region at 0x{:08x} exceeds the address space.",
+                region.start.0
+            ))?;
+            if region.irq_line.is_some() && !matches!(region.region_type, RegionTypeConfig::Timer) {
+                return Err(anyhow!(
+                    "Memory region at 0x{:08x}: irq_line is only valid for timers.",
+                    region.start.0
+                ));
+            }
+            let region_type = match region.region_type {
+                RegionTypeConfig::Ram => MemoryRegionType::RAM,
+                RegionTypeConfig::Rom => MemoryRegionType::ROM,
+                RegionTypeConfig::Uart => {
+                    let uart = Rc::new(RefCell::new(Uart::new()));
+                    uarts.push(uart.clone());
+                    MemoryRegionType::Device(uart)
+                }
+                RegionTypeConfig::Timer => {
+                    let irq_line = region.irq_line.unwrap_or(timer::TIMER_IRQ_LINE);
+                    MemoryRegionType::Device(Rc::new(RefCell::new(Timer::new(irq_line))))
+                }
+            };
+            mem_regions.push(MemoryRegionConfig {
+                adr_range: region.start.0..end,
+                init: region.init.to_value_init()?,
+                region_type,
+            });
+        }
+
+        let config = DRVSimConfig {
+            entry: self.entry.map_or(0, |entry| entry.0), // Replaced by the ELF entry point.
+            mtvec: self.mtvec.0,
+            dvec: self.dvec.0,
+            mem_regions,
+            reg_init: self.reg_init.to_value_init()?,
+            fault_mode: match self.fault_mode {
+                FaultModeConfig::Error => FaultMode::Error,
+                FaultModeConfig::Trap => FaultMode::Trap,
+            },
+            zicsr: self.zicsr,
+        };
+        Ok((config, uarts))
+    }
+}
+
+// ==== Simulation =================================================================================
+
+fn run(args: &Args) -> Result<u8, anyhow::Error> {
+    let config_text = std::fs::read_to_string(&args.config)
+        .map_err(|e| anyhow!("Failed to read {}: {e}", args.config.display()))?;
+    let json = args.config.extension().is_some_and(|ext| ext == "json");
+    let config = ConfigFile::parse(&config_text, json)
+        .map_err(|e| anyhow!("Invalid config file {}: {e}", args.config.display()))?;
+
+    let (sim_config, uarts) = config.to_sim_config()?;
+    let mut sim = DRVSim::new(sim_config);
+    let elf_entry = sim.load_elf(args.elf.clone())?;
+    if config.entry.is_none() {
+        sim.set_pc(elf_entry);
+    }
+
+    // Forward UART output to stderr after every instruction, so that stdout only holds the trace.
+    // Stop if the trace cannot be written, such as when stdout is piped into head:
+    let symbols = sim.symbols().clone();
+    let mut out = std::io::stdout();
+    let mut err = std::io::stderr();
+    let reason = sim.run_until(args.max_steps, |log| {
+        for uart in &uarts {
+            let mut uart = uart.borrow_mut();
+            if !uart.output_bytes().is_empty() {
+                _ = err.write_all(uart.output_bytes()).and_then(|_| err.flush());
+                uart.clear_output();
+            }
+        }
+        if args.quiet {
+            return false;
+        }
+        let line = if args.symbolize {
+            log.to_symbolized_log_string(&symbols)
+        } else {
+            log.to_log_string()
+        };
+        writeln!(out, "{line}").is_err()
+    });
+
+    match reason {
+        HaltReason::Ebreak(_) | HaltReason::SelfLoop(_) => match sim.peek_register(Register::X10) {
+            Some(status) if status <= MAX_EXIT_STATUS => Ok(status as u8),
+            Some(status) => Err(anyhow!(
+                "Exit status in a0 ({status}) exceeds {MAX_EXIT_STATUS}."
+            )),
+            None => Err(anyhow!("No exit status: a0 is uninitialized.")),
+        },
+        HaltReason::StepLimit => Err(anyhow!(
+            "Instruction limit of {} reached at PC 0x{:08x}.",
+            args.max_steps,
+            sim.pc()
+        )),
+        HaltReason::Predicate => Err(anyhow!("Failed to write the instruction trace.")),
+        HaltReason::Error(e) => Err(e),
+        reason => Err(anyhow!("Simulation halted unexpectedly: {reason:?}")),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(status) => ExitCode::from(status),
+        Err(e) => {
+            eprintln!("drv-sim: {e}");
+            ExitCode::from(EXIT_SIM_FAILURE)
+        }
+    }
+}
+
+// ==== Config File Tests ==========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_toml() {
+        let text = r#"
+            mtvec = 0x1000004
+            reg_init = { fixed_word = "0xDEAD_BEEF" }
+            fault_mode = "trap"
+
+            [[mem_regions]]
+            start = 0x1000000
+            size = 0x8000
+            type = "rom"
+
+            [[mem_regions]]
+            start = "0x2000000"
+            size = 0x8000
+            type = "ram"
+            init = "zero"
+
+            [[mem_regions]]
+            start = 0x3000000
+            size = 0x10
+            type = "timer"
+            irq_line = 3
+        "#;
+        let config = ConfigFile::parse(text, false)
+            .unwrap()
+            .to_sim_config()
+            .unwrap()
+            .0;
+        assert_eq!(config.entry, 0);
+        assert_eq!(config.mtvec, 0x1000004);
+        assert_eq!(config.dvec, 0);
+        assert!(matches!(config.reg_init, ValueInit::FixedWord(0xDEADBEEF)));
+        assert_eq!(config.fault_mode, FaultMode::Trap);
+        assert!(!config.zicsr);
+        assert_eq!(config.mem_regions.len(), 3);
+        assert_eq!(config.mem_regions[1].adr_range, 0x2000000..0x2008000);
+        assert!(matches!(config.mem_regions[0].init, ValueInit::Error));
+        assert!(matches!(config.mem_regions[1].init, ValueInit::Zero));
+        assert!(matches!(
+            config.mem_regions[2].region_type,
+            MemoryRegionType::Device(_)
+        ));
+    }
+
+    #[test]
+    fn config_json() {
+        let text = r#"{
+            "entry": "0x1000000",
+            "zicsr": true,
+            "mem_regions": [
+                { "start": 16777216, "size": 32768, "type": "rom", "init": { "fixed_byte": 171 } },
+                { "start": "0x4000000", "size": "0x10", "type": "uart" }
+            ]
+        }"#;
+        let config = ConfigFile::parse(text, true)
+            .unwrap()
+            .to_sim_config()
+            .unwrap()
+            .0;
+        assert_eq!(config.entry, 0x1000000);
+        assert!(config.zicsr);
+        assert!(matches!(
+            config.mem_regions[0].init,
+            ValueInit::FixedByte(0xAB)
+        ));
+        assert_eq!(config.mem_regions[1].adr_range, 0x4000000..0x4000010);
+    }
+
+    #[test]
+    fn config_invalid() {
+        let invalid = [
+            "mem_regions = []\nfoo = 1",
+            "[[mem_regions]]\nstart = 0\nsize = 4\ntype = \"flash\"",
+            "[[mem_regions]]\nstart = \"0xZZ\"\nsize = 4\ntype = \"ram\"",
+        ];
+        for text in invalid {
+            assert!(ConfigFile::parse(text, false).is_err(), "{text}");
+        }
+
+        let invalid = [
+            "[[mem_regions]]\nstart = 0xFFFFFFF0\nsize = 0x20\ntype = \"ram\"",
+            "[[mem_regions]]\nstart = 0\nsize = 4\ntype = \"ram\"\nirq_line = 1",
+            "reg_init = { fixed_byte = 256 }\nmem_regions = []",
+        ];
+        for text in invalid {
+            let config = ConfigFile::parse(text, false).unwrap();
+            assert!(config.to_sim_config().is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::ops::Range;
+
+use crate::DRVSim;
+
+// ==== Type Definitions ===========================================================================
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BreakpointId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WatchKind {
+    Read,   // Trigger on loads.
+    Write,  // Trigger on stores.
+    Access, // Trigger on loads and stores.
+}
+
+// Memory access that triggered a watchpoint:
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MemAccess {
+    pub adr: u32,
+    pub bytes: u32,
+    pub val: u32,
+    pub write: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BreakpointHit {
+    pub id: BreakpointId,
+    pub pc: u32, // Address of the instruction that triggered the breakpoint.
+    pub access: Option<MemAccess>, // Memory access, if triggered by a watchpoint.
+}
+
+struct Watchpoint {
+    id: BreakpointId,
+    adr_range: Range<u32>,
+    kind: WatchKind,
+    condition: Option<Box<dyn Fn(u32) -> bool>>, // Only trigger if true for the accessed value.
+}
+
+#[derive(Default)]
+pub(crate) struct Breakpoints {
+    next_id: u32,
+    pc: Vec<(BreakpointId, u32)>,
+    watch: Vec<Watchpoint>,
+    pub hit: Option<BreakpointHit>, // First watchpoint triggered during the current step.
+    pub resume_pc: Option<u32>,     // PC breakpoint the last run halted on, skipped when resuming.
+}
+
+// ==== Breakpoints Implementation =================================================================
+
+impl Breakpoints {
+    fn new_id(&mut self) -> BreakpointId {
+        let id = BreakpointId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub fn pc_breakpoint(&self, pc: u32) -> Option<BreakpointId> {
+        self.pc
+            .iter()
+            .find(|(_, adr)| *adr == pc)
+            .map(|(id, _)| *id)
+    }
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Halt execution before the instruction at pc is executed.
+    pub fn add_breakpoint(&mut self, pc: u32) -> BreakpointId {
+        let id = self.breakpoints.new_id();
+        self.breakpoints.pc.push((id, pc));
+        id
+    }
+
+    // Halt execution after an instruction accesses memory in adr_range.
+    pub fn add_watchpoint(&mut self, adr_range: Range<u32>, kind: WatchKind) -> BreakpointId {
+        let id = self.breakpoints.new_id();
+        self.breakpoints.watch.push(Watchpoint {
+            id,
+            adr_range,
+            kind,
+            condition: None,
+        });
+        id
+    }
+
+    // Halt execution after an instruction accesses memory in adr_range, if the condition holds
+    // for the value loaded or stored.
+    pub fn add_conditional_watchpoint(
+        &mut self,
+        adr_range: Range<u32>,
+        kind: WatchKind,
+        condition: impl Fn(u32) -> bool + 'static,
+    ) -> BreakpointId {
+        let id = self.breakpoints.new_id();
+        self.breakpoints.watch.push(Watchpoint {
+            id,
+            adr_range,
+            kind,
+            condition: Some(Box::new(condition)),
+        });
+        id
+    }
+
+    // Remove a breakpoint or watchpoint. Returns false if it did not exist.
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        let count = self.breakpoints.pc.len() + self.breakpoints.watch.len();
+        self.breakpoints.pc.retain(|(bp_id, _)| *bp_id != id);
+        self.breakpoints.watch.retain(|wp| wp.id != id);
+        count != self.breakpoints.pc.len() + self.breakpoints.watch.len()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.pc.clear();
+        self.breakpoints.watch.clear();
+    }
+
+    // Record the first watchpoint triggered by a memory access.
+    pub(crate) fn check_watchpoints(&mut self, access: MemAccess) {
+        if self.breakpoints.watch.is_empty() || self.breakpoints.hit.is_some() {
+            return;
+        }
+
+        let access_end = access.adr.saturating_add(access.bytes);
+        for wp in self.breakpoints.watch.iter() {
+            let kind_matches = match wp.kind {
+                WatchKind::Read => !access.write,
+                WatchKind::Write => access.write,
+                WatchKind::Access => true,
+            };
+            let overlaps = access.adr < wp.adr_range.end && wp.adr_range.start < access_end;
+            let condition_holds = match &wp.condition {
+                Some(condition) => condition(access.val),
+                None => true,
+            };
+
+            if kind_matches && overlaps && condition_holds {
+                self.breakpoints.hit = Some(BreakpointHit {
+                    id: wp.id,
+                    pc: self.pc,
+                    access: Some(access),
+                });
+                return;
+            }
+        }
+    }
+}
+
+// ==== Breakpoint Tests ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{breakpoint::*, inst::Register, run::HaltReason, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator(insts: Vec<u32>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Zero,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    // Stores x1+1, x1+2, ... to RAM_START, RAM_START+4, ..., with x2 = RAM_START:
+    fn store_loop() -> Vec<u32> {
+        vec![
+            0x02000137, // LUI x2, 0x2000
+            0x00108093, // ADDI x1, x1, 1
+            0x00112023, // SW x1, 0x0(x2)
+            0x00410113, // ADDI x2, x2, 4
+            0xff5ff06f, // JAL x0, .-12
+        ]
+    }
+
+    #[test]
+    fn pc_breakpoint() {
+        let mut sim = new_simulator(store_loop());
+        let id = sim.add_breakpoint(ROM_START + 8);
+
+        let reason = sim.run(100);
+        assert!(
+            matches!(
+                reason,
+                HaltReason::Breakpoint(BreakpointHit { id: hit, pc, access: None })
+                    if hit == id && pc == ROM_START + 8
+            ),
+            "{reason:?}"
+        );
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 1);
+
+        // Resume from the breakpoint:
+        let reason = sim.run(100);
+        assert!(matches!(reason, HaltReason::Breakpoint(_)));
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+
+        assert!(sim.remove_breakpoint(id));
+        assert!(!sim.remove_breakpoint(id));
+        assert!(matches!(sim.run(100), HaltReason::StepLimit));
+    }
+
+    #[test]
+    fn entry_breakpoint() {
+        let mut sim = new_simulator(store_loop());
+        let id = sim.add_breakpoint(ROM_START);
+
+        // Breakpoints at the initial PC trigger on the first run:
+        let reason = sim.run(100);
+        assert!(
+            matches!(reason, HaltReason::Breakpoint(hit) if hit.id == id && hit.pc == ROM_START),
+            "{reason:?}"
+        );
+        assert_eq!(sim.pc(), ROM_START);
+
+        // Resuming skips the breakpoint, unless the PC was changed:
+        sim.set_pc(ROM_START);
+        assert!(matches!(sim.run(100), HaltReason::Breakpoint(_)));
+        assert!(matches!(sim.run(100), HaltReason::StepLimit));
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut sim = new_simulator(store_loop());
+        sim.add_watchpoint(RAM_START + 0x0E..RAM_START + 0x0F, WatchKind::Read);
+        let id = sim.add_watchpoint(RAM_START + 0x0E..RAM_START + 0x0F, WatchKind::Write);
+
+        let reason = sim.run(100);
+        let HaltReason::Breakpoint(hit) = reason else {
+            panic!("Expected breakpoint, got {reason:?}!");
+        };
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.pc, ROM_START + 8);
+        assert_eq!(
+            hit.access,
+            Some(MemAccess {
+                adr: RAM_START + 0xC,
+                bytes: 4,
+                val: 4,
+                write: true
+            })
+        );
+    }
+
+    #[test]
+    fn conditional_watchpoint() {
+        let mut sim = new_simulator(store_loop());
+        sim.add_conditional_watchpoint(RAM_START..RAM_START + 0x100, WatchKind::Access, |val| {
+            val == 7
+        });
+
+        let reason = sim.run(100);
+        assert!(matches!(reason, HaltReason::Breakpoint(_)), "{reason:?}");
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 7);
+        assert_eq!(
+            sim.read_register(Register::X2).unwrap().val,
+            RAM_START + 0x18
+        );
+    }
+}
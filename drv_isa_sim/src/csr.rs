@@ -0,0 +1,190 @@
+use crate::{inst::Register, inst_log::Value, DRVSim};
+
+// ==== CSR Addresses ==============================================================================
+
+pub const MSTATUS: u32 = 0x300;
+pub const MISA: u32 = 0x301;
+pub const MIE: u32 = 0x304;
+pub const MTVEC: u32 = 0x305;
+pub const MSCRATCH: u32 = 0x340;
+pub const MEPC: u32 = 0x341;
+pub const MCAUSE: u32 = 0x342;
+pub const MTVAL: u32 = 0x343;
+pub const MIP: u32 = 0x344;
+pub const MCYCLE: u32 = 0xB00;
+pub const MINSTRET: u32 = 0xB02;
+pub const MCYCLEH: u32 = 0xB80;
+pub const MINSTRETH: u32 = 0xB82;
+pub const CYCLE: u32 = 0xC00;
+pub const INSTRET: u32 = 0xC02;
+pub const CYCLEH: u32 = 0xC80;
+pub const INSTRETH: u32 = 0xC82;
+pub const MHARTID: u32 = 0xF14;
+
+const MSTATUS_MIE: u32 = 1 << 3;
+const MSTATUS_MPIE: u32 = 1 << 7;
+const MSTATUS_MPP: u32 = 0b11 << 11;
+
+// ==== Type Definitions ===========================================================================
+
+// Machine-mode CSR state that is not already part of the core state. mepc is the DRV Xmpc
+// register, while the interrupt enable and pending bits in mstatus, mie and mip reflect the
+// interrupt state of the core.
+#[derive(Clone)]
+pub(crate) struct CsrFile {
+    pub mtvec: u32,
+    pub mscratch: u32,
+    pub mcause: u32,
+    pub mtval: u32,
+    pub mpie: bool,
+    pub mcycle: u64,
+    pub minstret: u64,
+}
+
+// Access to an unknown CSR or write to a read-only CSR, which is an illegal instruction:
+#[derive(Debug, Clone, Copy)]
+pub enum IllegalCsrAccess {
+    UnknownRead { csr: u32 },
+    UnknownWrite { csr: u32 },
+    ReadOnly { csr: u32 },
+}
+
+// ==== CsrFile Implementation =====================================================================
+
+impl CsrFile {
+    pub fn new(mtvec: u32) -> CsrFile {
+        CsrFile {
+            mtvec,
+            mscratch: 0,
+            mcause: 0,
+            mtval: 0,
+            mpie: false,
+            mcycle: 0,
+            minstret: 0,
+        }
+    }
+}
+
+// Name of a known CSR:
+pub fn csr_name(csr: u32) -> Option<&'static str> {
+    match csr {
+        MSTATUS => Some("mstatus"),
+        MISA => Some("misa"),
+        MIE => Some("mie"),
+        MTVEC => Some("mtvec"),
+        MSCRATCH => Some("mscratch"),
+        MEPC => Some("mepc"),
+        MCAUSE => Some("mcause"),
+        MTVAL => Some("mtval"),
+        MIP => Some("mip"),
+        MCYCLE => Some("mcycle"),
+        MINSTRET => Some("minstret"),
+        MCYCLEH => Some("mcycleh"),
+        MINSTRETH => Some("minstreth"),
+        CYCLE => Some("cycle"),
+        INSTRET => Some("instret"),
+        CYCLEH => Some("cycleh"),
+        INSTRETH => Some("instreth"),
+        MHARTID => Some("mhartid"),
+        _ => None,
+    }
+}
+
+// ==== IllegalCsrAccess Implementation ===========================================================
+
+impl std::fmt::Display for IllegalCsrAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IllegalCsrAccess::UnknownRead { csr } => {
+                write!(f, "Attempted to read unknown CSR 0x{csr:03x}.")
+            }
+            IllegalCsrAccess::UnknownWrite { csr } => {
+                write!(f, "Attempted to write unknown CSR 0x{csr:03x}.")
+            }
+            IllegalCsrAccess::ReadOnly { csr } => {
+                write!(f, "Attempted to write read-only CSR 0x{csr:03x}.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IllegalCsrAccess {}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    pub fn read_csr(&mut self, csr: u32) -> Result<Value, anyhow::Error> {
+        let val = match csr {
+            MSTATUS => {
+                let mut val = MSTATUS_MPP; // Machine mode only.
+                if self.irq_global_enable {
+                    val |= MSTATUS_MIE;
+                }
+                if self.csr.mpie {
+                    val |= MSTATUS_MPIE;
+                }
+                val
+            }
+            MISA => (0b01 << 30) | (1 << 4) | (1 << 8), // RV32, E, I
+            MIE => self.irq_enable,
+            MTVEC => self.csr.mtvec,
+            MSCRATCH => self.csr.mscratch,
+            MEPC => self.read_register(Register::Xmpc)?.val,
+            MCAUSE => self.csr.mcause,
+            MTVAL => self.csr.mtval,
+            MIP => self.irq_pending(),
+            MCYCLE | CYCLE => self.csr.mcycle as u32,
+            MINSTRET | INSTRET => self.csr.minstret as u32,
+            MCYCLEH | CYCLEH => (self.csr.mcycle >> 32) as u32,
+            MINSTRETH | INSTRETH => (self.csr.minstret >> 32) as u32,
+            MHARTID => 0,
+            _ => return Err(IllegalCsrAccess::UnknownRead { csr }.into()),
+        };
+        Ok(Value::csr_value(csr, val))
+    }
+
+    pub fn write_csr(&mut self, csr: u32, val: u32) -> Result<Value, anyhow::Error> {
+        // The top two bits of the address indicate read-only CSRs:
+        if (csr >> 10) & 0b11 == 0b11 {
+            return Err(IllegalCsrAccess::ReadOnly { csr }.into());
+        }
+
+        match csr {
+            MSTATUS => {
+                self.irq_global_enable = (val & MSTATUS_MIE) != 0;
+                self.csr.mpie = (val & MSTATUS_MPIE) != 0;
+            }
+            MISA => (), // Not writable.
+            MIE => self.irq_enable = val,
+            MTVEC => self.csr.mtvec = val & !0b11, // Direct mode only.
+            MSCRATCH => self.csr.mscratch = val,
+            MEPC => {
+                self.write_register(Register::Xmpc, val);
+            }
+            MCAUSE => self.csr.mcause = val,
+            MTVAL => self.csr.mtval = val,
+            MIP => (), // Interrupt lines are driven externally.
+            MCYCLE => self.csr.mcycle = (self.csr.mcycle & !0xFFFFFFFF) | (val as u64),
+            MINSTRET => self.csr.minstret = (self.csr.minstret & !0xFFFFFFFF) | (val as u64),
+            MCYCLEH => self.csr.mcycle = (self.csr.mcycle & 0xFFFFFFFF) | ((val as u64) << 32),
+            MINSTRETH => {
+                self.csr.minstret = (self.csr.minstret & 0xFFFFFFFF) | ((val as u64) << 32)
+            }
+            _ => return Err(IllegalCsrAccess::UnknownWrite { csr }.into()),
+        };
+
+        // Report the stored value, which differs from the written one for WARL and read-only
+        // bits:
+        self.read_csr(csr)
+    }
+
+    // Number of cycles simulated. Every instruction takes a single cycle.
+    pub fn cycle(&self) -> u64 {
+        self.csr.mcycle
+    }
+
+    // Number of instructions retired.
+    pub fn instret(&self) -> u64 {
+        self.csr.minstret
+    }
+}
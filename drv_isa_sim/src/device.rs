@@ -0,0 +1,149 @@
+// ==== Device Trait ===============================================================================
+
+// Memory-mapped peripheral, attached to the simulator with MemoryRegionType::Device. Accesses
+// are given as offsets from the start of the device's memory region, and are 1, 2 or 4 bytes
+// wide. Unsupported accesses should be reported as errors. Devices may drive interrupt lines,
+// which are combined with the lines raised through DRVSim::raise_irq.
+pub trait Device {
+    fn read(&mut self, offset: u32, bytes: u32) -> Result<u32, anyhow::Error>;
+
+    fn write(&mut self, offset: u32, bytes: u32, val: u32) -> Result<(), anyhow::Error>;
+
+    // Called once after every simulated step.
+    fn tick(&mut self) {}
+
+    // Mask of interrupt lines asserted by the device, sampled after every tick.
+    fn irq_lines(&self) -> u32 {
+        0
+    }
+}
+
+// ==== Device Tests ===============================================================================
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anyhow::anyhow;
+
+    use crate::{device::*, inst::Register, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const DEV_START: u32 = 0x3000000;
+
+    // Register file with a cycle counter at offset 0x8:
+    #[derive(Default)]
+    struct TestDevice {
+        regs: [u32; 2],
+        ticks: u32,
+        accesses: Vec<(u32, u32, Option<u32>)>,
+    }
+
+    impl Device for TestDevice {
+        fn read(&mut self, offset: u32, bytes: u32) -> Result<u32, anyhow::Error> {
+            self.accesses.push((offset, bytes, None));
+            match offset {
+                0x0 | 0x4 => Ok(self.regs[(offset / 4) as usize]),
+                0x8 => Ok(self.ticks),
+                _ => Err(anyhow!("Invalid test device register 0x{offset:x}.")),
+            }
+        }
+
+        fn write(&mut self, offset: u32, bytes: u32, val: u32) -> Result<(), anyhow::Error> {
+            self.accesses.push((offset, bytes, Some(val)));
+            match offset {
+                0x0 | 0x4 => self.regs[(offset / 4) as usize] = val,
+                _ => return Err(anyhow!("Invalid test device register 0x{offset:x}.")),
+            }
+            Ok(())
+        }
+
+        fn tick(&mut self) {
+            self.ticks += 1;
+        }
+    }
+
+    fn new_simulator(insts: Vec<u32>, device: Rc<RefCell<TestDevice>>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: DEV_START..DEV_START + 0x10,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::Device(device),
+                },
+            ],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    #[test]
+    fn device_access() {
+        let device = Rc::new(RefCell::new(TestDevice::default()));
+        let mut sim = new_simulator(
+            vec![
+                0x030000b7, // LUI x1, 0x3000
+                0x02a00113, // ADDI x2, x0, 42
+                0x0020a223, // SW x2, 0x4(x1)
+                0x0040a183, // LW x3, 0x4(x1)
+                0x00209023, // SH x2, 0x0(x1)
+                0x0080c203, // LBU x4, 0x8(x1)
+            ],
+            device.clone(),
+        );
+
+        for _ in 0..6 {
+            sim.step().unwrap();
+        }
+
+        assert_eq!(sim.read_register(Register::X3).unwrap().val, 42);
+        assert_eq!(sim.read_register(Register::X4).unwrap().val, 5);
+        assert_eq!(device.borrow().regs, [42, 42]);
+        assert_eq!(device.borrow().ticks, 6);
+        assert_eq!(
+            device.borrow().accesses,
+            vec![
+                (0x4, 4, Some(42)),
+                (0x4, 4, None),
+                (0x0, 2, Some(42)),
+                (0x8, 1, None)
+            ]
+        );
+
+        // Device errors stop the simulation:
+        assert!(sim.read_w(DEV_START + 0xC).is_err());
+        assert!(sim.write_w(DEV_START + 0x8, 0).is_err());
+
+        // Accesses may not cross into or out of a device:
+        assert!(sim.read_w(DEV_START + 0xE).is_err());
+    }
+
+    #[test]
+    fn device_program() {
+        let device = Rc::new(RefCell::new(TestDevice::default()));
+        let mut sim = new_simulator(vec![], device.clone());
+
+        sim.program_b(DEV_START + 0x4, 0xAB).unwrap();
+        assert_eq!(device.borrow().regs, [0, 0xAB]);
+        assert_eq!(sim.read_b(DEV_START + 0x4).unwrap().val, 0xAB);
+
+        // Devices cannot be peeked:
+        assert!(sim.peek_b(DEV_START + 0x4).is_err());
+
+        // Device state cannot be serialized:
+        assert!(sim.snapshot().to_bytes().is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::ops::Range;
+
+use anyhow::anyhow;
+
+use crate::{DRVSim, RegionBacking};
+
+// Data bytes per Intel HEX record:
+const IHEX_RECORD_LEN: u32 = 16;
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Contents of an address range, which must lie within a single memory region. Uninitialized
+    // bytes are replaced by the fill value.
+    pub fn export_bin(&self, range: Range<u32>, fill: u8) -> Result<Vec<u8>, anyhow::Error> {
+        if range.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let region = self
+            .mems
+            .iter()
+            .find(|region| {
+                region.adr_range.contains(&range.start)
+                    && region.adr_range.contains(&(range.end - 1))
+            })
+            .ok_or(anyhow!(
+                "Range 0x{:08x}..0x{:08x} does not lie within a single memory region.",
+                range.start,
+                range.end
+            ))?;
+
+        let RegionBacking::Memory(mem) = &region.backing else {
+            return Err(anyhow!("Device memory regions cannot be exported."));
+        };
+
+        Ok(range.map(|adr| mem.peek_b(adr).unwrap_or(fill)).collect())
+    }
+
+    // Contents of an address range, split into byte lanes of a bus that is lanes bytes wide. Lane
+    // n holds every byte with an address offset (from the start of the range) of n modulo lanes.
+    pub fn export_byte_lanes(
+        &self,
+        range: Range<u32>,
+        lanes: u32,
+        fill: u8,
+    ) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        if lanes == 0 || !range.len().is_multiple_of(lanes as usize) {
+            return Err(anyhow!(
+                "Range length must be a multiple of the number of byte lanes."
+            ));
+        }
+
+        let data = self.export_bin(range, fill)?;
+        Ok((0..lanes as usize)
+            .map(|lane| {
+                data.iter()
+                    .skip(lane)
+                    .step_by(lanes as usize)
+                    .copied()
+                    .collect()
+            })
+            .collect())
+    }
+
+    // Contents of an address range as a Verilog $readmemh image with one little endian word of
+    // word_bytes (1, 2 or 4) bytes per line, starting at word zero.
+    pub fn export_readmemh(
+        &self,
+        range: Range<u32>,
+        word_bytes: u32,
+        fill: u8,
+    ) -> Result<String, anyhow::Error> {
+        if ![1, 2, 4].contains(&word_bytes) {
+            return Err(anyhow!(
+                "Invalid $readmemh word size of {word_bytes} bytes."
+            ));
+        }
+        if !range.len().is_multiple_of(word_bytes as usize) {
+            return Err(anyhow!("Range length must be a multiple of the word size."));
+        }
+
+        let data = self.export_bin(range, fill)?;
+        let mut result = String::new();
+        for word in data.chunks(word_bytes as usize) {
+            let val = word
+                .iter()
+                .rev()
+                .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+            result.push_str(&format!(
+                "{:0width$x}\n",
+                val,
+                width = (word_bytes * 2) as usize
+            ));
+        }
+        Ok(result)
+    }
+
+    // Contents of an address range as an Intel HEX image, at its absolute address.
+    pub fn export_ihex(&self, range: Range<u32>, fill: u8) -> Result<String, anyhow::Error> {
+        let data = self.export_bin(range.clone(), fill)?;
+
+        let mut result = String::new();
+        let mut upper_adr = None;
+        let mut offset = 0;
+        while offset < data.len() {
+            let adr = range.start + offset as u32;
+
+            // Records may not cross a 64KiB boundary:
+            if upper_adr != Some(adr >> 16) {
+                upper_adr = Some(adr >> 16);
+                push_ihex_record(&mut result, 0, 0x04, &((adr >> 16) as u16).to_be_bytes());
+            }
+            let len = u32::min(IHEX_RECORD_LEN, 0x10000 - (adr & 0xFFFF)) as usize;
+            let len = usize::min(len, data.len() - offset);
+
+            push_ihex_record(&mut result, adr as u16, 0x00, &data[offset..offset + len]);
+            offset += len;
+        }
+        push_ihex_record(&mut result, 0, 0x01, &[]);
+
+        Ok(result)
+    }
+}
+
+fn push_ihex_record(result: &mut String, adr: u16, record_type: u8, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(adr.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |acc, b| acc.wrapping_sub(*b));
+    bytes.push(checksum);
+
+    result.push(':');
+    for byte in bytes {
+        result.push_str(&format!("{:02X}", byte));
+    }
+    result.push('\n');
+}
+
+// ==== Export Tests ===============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator() -> DRVSim {
+        DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x20000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        })
+    }
+
+    #[test]
+    fn export_bin_and_lanes() {
+        let mut sim = new_simulator();
+        sim.program_w(ROM_START, 0x03020100).unwrap();
+        sim.program_b(ROM_START + 0x5, 0x05).unwrap();
+
+        assert_eq!(
+            sim.export_bin(ROM_START..ROM_START + 8, 0xFF).unwrap(),
+            vec![0x00, 0x01, 0x02, 0x03, 0xFF, 0x05, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            sim.export_byte_lanes(ROM_START..ROM_START + 8, 2, 0xFF)
+                .unwrap(),
+            vec![vec![0x00, 0x02, 0xFF, 0xFF], vec![0x01, 0x03, 0x05, 0xFF]]
+        );
+
+        // Exports do not initialize memory:
+        assert!(sim.read_b(ROM_START + 0x4).is_err());
+
+        assert!(sim.export_bin(ROM_START..RAM_START + 4, 0).is_err());
+        assert!(sim
+            .export_byte_lanes(ROM_START..ROM_START + 5, 2, 0)
+            .is_err());
+        assert!(sim.export_bin(ROM_START..ROM_START, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn export_readmemh() {
+        let mut sim = new_simulator();
+        sim.program_w(ROM_START, 0x00100093).unwrap();
+        sim.program_w(ROM_START + 4, 0x0000006f).unwrap();
+
+        let text = sim
+            .export_readmemh(ROM_START..ROM_START + 12, 4, 0)
+            .unwrap();
+        assert_eq!(text, "00100093\n0000006f\n00000000\n");
+        let text = sim.export_readmemh(ROM_START..ROM_START + 4, 2, 0).unwrap();
+        assert_eq!(text, "0093\n0010\n");
+        assert!(sim.export_readmemh(ROM_START..ROM_START + 6, 4, 0).is_err());
+
+        // Round trip:
+        let text = sim.export_readmemh(ROM_START..ROM_START + 8, 1, 0).unwrap();
+        sim.load_readmemh_str(&text, RAM_START, 1).unwrap();
+        assert_eq!(sim.read_w(RAM_START + 4).unwrap().val, 0x0000006f);
+    }
+
+    #[test]
+    fn export_ihex() {
+        let mut sim = new_simulator();
+        sim.program_w(ROM_START, 0x00100093).unwrap();
+
+        let text = sim.export_ihex(ROM_START..ROM_START + 4, 0).unwrap();
+        assert_eq!(text, ":020000040100F9\n:040000009300100059\n:00000001FF\n");
+
+        // Records split at 64KiB boundaries:
+        for adr in ROM_START + 0xFFF8..ROM_START + 0x10008 {
+            sim.program_b(adr, adr as u8).unwrap();
+        }
+        let range = ROM_START + 0xFFF8..ROM_START + 0x10008;
+        let text = sim.export_ihex(range.clone(), 0).unwrap();
+        assert_eq!(text.lines().count(), 5);
+
+        let mut other = new_simulator();
+        other.load_ihex_str(&text, 0).unwrap();
+        assert_eq!(
+            other.export_bin(range.clone(), 0).unwrap(),
+            sim.export_bin(range, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,891 @@
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    breakpoint::{BreakpointId, WatchKind},
+    inst::Register,
+    run::HaltReason,
+    DRVSim,
+};
+
+// ==== Constants ==================================================================================
+
+// Register numbering used in the target description: x0-x15, pc, Xmpc, Xdpc.
+const REG_PC: u32 = 16;
+const REG_XMPC: u32 = 17;
+const REG_XDPC: u32 = 18;
+const REG_COUNT: u32 = 19;
+
+// Number of instructions simulated between checks for an interrupt request from gdb:
+const INTERRUPT_POLL_STEPS: u64 = 10000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>riscv:rv32</architecture>
+  <feature name="org.gnu.gdb.riscv.cpu">
+    <reg name="x0" bitsize="32" type="int" regnum="0"/>
+    <reg name="x1" bitsize="32" type="code_ptr" regnum="1"/>
+    <reg name="x2" bitsize="32" type="data_ptr" regnum="2"/>
+    <reg name="x3" bitsize="32" type="data_ptr" regnum="3"/>
+    <reg name="x4" bitsize="32" type="data_ptr" regnum="4"/>
+    <reg name="x5" bitsize="32" type="int" regnum="5"/>
+    <reg name="x6" bitsize="32" type="int" regnum="6"/>
+    <reg name="x7" bitsize="32" type="int" regnum="7"/>
+    <reg name="x8" bitsize="32" type="int" regnum="8"/>
+    <reg name="x9" bitsize="32" type="int" regnum="9"/>
+    <reg name="x10" bitsize="32" type="int" regnum="10"/>
+    <reg name="x11" bitsize="32" type="int" regnum="11"/>
+    <reg name="x12" bitsize="32" type="int" regnum="12"/>
+    <reg name="x13" bitsize="32" type="int" regnum="13"/>
+    <reg name="x14" bitsize="32" type="int" regnum="14"/>
+    <reg name="x15" bitsize="32" type="int" regnum="15"/>
+    <reg name="pc" bitsize="32" type="code_ptr" regnum="16"/>
+  </feature>
+  <feature name="org.drv.debug">
+    <reg name="xmpc" bitsize="32" type="code_ptr" regnum="17" group="system"/>
+    <reg name="xdpc" bitsize="32" type="code_ptr" regnum="18" group="system"/>
+  </feature>
+</target>
+"#;
+
+// ==== Type Definitions ===========================================================================
+
+// GDB remote serial protocol server, debugging a simulator over a single connection.
+pub struct GdbServer<'a> {
+    sim: &'a mut DRVSim,
+    stream: TcpStream,
+    no_ack: bool,          // Packet acknowledgement disabled via QStartNoAckMode.
+    pending: VecDeque<u8>, // Bytes received while polling for interrupts, read before the stream.
+    breakpoints: Vec<GdbBreakpoint>,
+}
+
+struct GdbBreakpoint {
+    kind: u8, // Z packet type: 0 = software, 1 = hardware, 2 = write, 3 = read, 4 = access.
+    adr: u32,
+    len: u32,
+    id: BreakpointId,
+}
+
+// ==== Server Entry Points ========================================================================
+
+// Wait for gdb to connect to the given address, and serve it until it detaches or kills the
+// target. The bound address is passed to on_bound before waiting, such as to report the port
+// picked for "localhost:0".
+pub fn listen(
+    sim: &mut DRVSim,
+    adr: impl ToSocketAddrs,
+    on_bound: impl FnOnce(SocketAddr),
+) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(adr)?;
+    on_bound(listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    GdbServer::new(sim, stream).serve()
+}
+
+// ==== GdbServer Implementation ===================================================================
+
+impl<'a> GdbServer<'a> {
+    pub fn new(sim: &'a mut DRVSim, stream: TcpStream) -> GdbServer<'a> {
+        GdbServer {
+            sim,
+            stream,
+            no_ack: false,
+            pending: VecDeque::new(),
+            breakpoints: vec![],
+        }
+    }
+
+    // Handle packets until gdb detaches, kills the target or disconnects. Breakpoints set by gdb
+    // are removed once the server is dropped, including after an error.
+    pub fn serve(mut self) -> Result<(), anyhow::Error> {
+        self.stream.set_nodelay(true)?;
+        while let Some(packet) = self.recv_packet()? {
+            match self.handle_packet(&packet)? {
+                Some(response) => self.send_packet(&response)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    // Handle a single packet, returning the response. None ends the session.
+    fn handle_packet(&mut self, packet: &str) -> Result<Option<String>, anyhow::Error> {
+        let response = match packet.as_bytes().first() {
+            Some(b'?') => String::from("S05"),
+            Some(b'g') => (0..REG_COUNT).map(|n| self.read_gdb_register(n)).collect(),
+            Some(b'G') => {
+                let data = &packet[1..];
+                for n in 0..REG_COUNT {
+                    match data.get((n as usize) * 8..(n as usize + 1) * 8) {
+                        Some(hex) if !hex.starts_with('x') => {
+                            let Some(val) = parse_le_word(hex) else {
+                                return Ok(Some(String::from("E01")));
+                            };
+                            self.write_gdb_register(n, val);
+                        }
+                        _ => (),
+                    }
+                }
+                String::from("OK")
+            }
+            Some(b'p') => match u32::from_str_radix(&packet[1..], 16) {
+                Ok(n) if n < REG_COUNT => self.read_gdb_register(n),
+                _ => String::from("E01"),
+            },
+            Some(b'P') => {
+                let Some((n, val)) = packet[1..].split_once('=') else {
+                    return Ok(Some(String::from("E01")));
+                };
+                match (u32::from_str_radix(n, 16), parse_le_word(val)) {
+                    (Ok(n), Some(val)) if n < REG_COUNT => {
+                        self.write_gdb_register(n, val);
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            Some(b'm') => {
+                let Some((adr, len)) = parse_adr_len(&packet[1..]) else {
+                    return Ok(Some(String::from("E01")));
+                };
+                // Peek, so that reads do not trigger watchpoints or device side effects. The reply
+                // ends at the first uninitialized or unmapped byte:
+                let mut data = String::new();
+                for offset in 0..len {
+                    match self.sim.peek_b(adr.wrapping_add(offset)) {
+                        Ok(Some(val)) => data.push_str(&format!("{val:02x}")),
+                        Ok(None) | Err(_) => break,
+                    }
+                }
+                if data.is_empty() && len != 0 {
+                    String::from("E01")
+                } else {
+                    data
+                }
+            }
+            Some(b'M') => {
+                let Some((adr_len, data)) = packet[1..].split_once(':') else {
+                    return Ok(Some(String::from("E01")));
+                };
+                let (Some((adr, len)), Some(data)) = (parse_adr_len(adr_len), parse_hex(data))
+                else {
+                    return Ok(Some(String::from("E01")));
+                };
+                if data.len() != len as usize {
+                    return Ok(Some(String::from("E01")));
+                }
+                let mut ok = true;
+                for (offset, val) in data.into_iter().enumerate() {
+                    ok &= self
+                        .sim
+                        .program_b(adr.wrapping_add(offset as u32), val)
+                        .is_ok();
+                }
+                String::from(if ok { "OK" } else { "E01" })
+            }
+            Some(b's') => {
+                self.resume_adr(&packet[1..]);
+                let reason = self.sim.run(1);
+                self.stop_reply(reason)?
+            }
+            Some(b'c') => {
+                self.resume_adr(&packet[1..]);
+                self.continue_execution()?
+            }
+            Some(b'b') if packet == "bs" => {
+                let reason = self.sim.reverse_continue(1);
+                self.stop_reply(reason)?
+            }
+            Some(b'b') if packet == "bc" => self.reverse_execution()?,
+            Some(b'Z') | Some(b'z') => self.handle_breakpoint_packet(packet),
+            Some(b'H') => String::from("OK"),
+            Some(b'T') => String::from("OK"),
+            Some(b'D') => {
+                self.send_packet("OK")?;
+                return Ok(None);
+            }
+            Some(b'k') => return Ok(None),
+            Some(b'q') | Some(b'Q') => self.handle_query(packet),
+            _ => String::new(), // Unsupported.
+        };
+        Ok(Some(response))
+    }
+
+    fn handle_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            String::from(concat!(
+                "PacketSize=1000;qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+;",
+                "ReverseStep+;ReverseContinue+"
+            ))
+        } else if packet == "QStartNoAckMode" {
+            // Takes effect after this packet has been acknowledged:
+            self.no_ack = true;
+            String::from("OK")
+        } else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_adr_len(args) else {
+                return String::from("E01");
+            };
+            let offset = (offset as usize).min(TARGET_XML.len());
+            let end = offset.saturating_add(len as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            format!("{marker}{}", &TARGET_XML[offset..end])
+        } else if packet == "qAttached" {
+            String::from("1")
+        } else if packet == "qC" {
+            String::from("QC1")
+        } else if packet == "qfThreadInfo" {
+            String::from("m1")
+        } else if packet == "qsThreadInfo" {
+            String::from("l")
+        } else {
+            String::new()
+        }
+    }
+
+    fn handle_breakpoint_packet(&mut self, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let mut args = packet[1..].split(',');
+        let (Some(kind), Some(adr), Some(len)) = (args.next(), args.next(), args.next()) else {
+            return String::from("E01");
+        };
+        let (Ok(kind), Ok(adr), Ok(len)) = (
+            kind.parse::<u8>(),
+            u32::from_str_radix(adr, 16),
+            // Ignore any conditions or commands following the kind:
+            u32::from_str_radix(len.split(';').next().unwrap_or(len), 16),
+        ) else {
+            return String::from("E01");
+        };
+
+        if !insert {
+            let idx = self
+                .breakpoints
+                .iter()
+                .position(|bp| bp.kind == kind && bp.adr == adr && bp.len == len);
+            if let Some(idx) = idx {
+                let bp = self.breakpoints.remove(idx);
+                self.sim.remove_breakpoint(bp.id);
+            }
+            return String::from("OK");
+        }
+
+        let watch_range = adr..adr.saturating_add(len.max(1));
+        let id = match kind {
+            0 | 1 => self.sim.add_breakpoint(adr),
+            2 => self.sim.add_watchpoint(watch_range, WatchKind::Write),
+            3 => self.sim.add_watchpoint(watch_range, WatchKind::Read),
+            4 => self.sim.add_watchpoint(watch_range, WatchKind::Access),
+            _ => return String::new(),
+        };
+        self.breakpoints.push(GdbBreakpoint { kind, adr, len, id });
+        String::from("OK")
+    }
+
+    // Continue until the simulation halts or gdb requests an interrupt.
+    fn continue_execution(&mut self) -> Result<String, anyhow::Error> {
+        // Poll for interrupts from within a single run, so that breakpoints are checked before
+        // every instruction:
+        let stream = &self.stream;
+        let pending = &mut self.pending;
+        let mut steps: u64 = 0;
+        let mut interrupt = Ok(false);
+        let reason = self.sim.run_until(u64::MAX, |_| {
+            steps += 1;
+            if !steps.is_multiple_of(INTERRUPT_POLL_STEPS) {
+                return false;
+            }
+            interrupt = interrupt_requested(stream, pending);
+            !matches!(interrupt, Ok(false))
+        });
+
+        match reason {
+            HaltReason::Predicate => interrupt.map(|_| String::from("S02")),
+            reason => self.stop_reply(reason),
+        }
+    }
+
+    // Step backwards until a breakpoint is reached, the undo journal is exhausted, or gdb
+    // requests an interrupt.
+    fn reverse_execution(&mut self) -> Result<String, anyhow::Error> {
+        loop {
+            match self.sim.reverse_continue(INTERRUPT_POLL_STEPS) {
+                HaltReason::StepLimit => {
+                    if interrupt_requested(&self.stream, &mut self.pending)? {
+                        return Ok(String::from("S02"));
+                    }
+                }
+                reason => return self.stop_reply(reason),
+            }
+        }
+    }
+
+    fn stop_reply(&mut self, reason: HaltReason) -> Result<String, anyhow::Error> {
+        Ok(match reason {
+            HaltReason::Breakpoint(hit) => {
+                let bp = self.breakpoints.iter().find(|bp| bp.id == hit.id);
+                match (bp.map(|bp| bp.kind), hit.access) {
+                    (Some(2), Some(access)) => format!("T05watch:{:x};", access.adr),
+                    (Some(3), Some(access)) => format!("T05rwatch:{:x};", access.adr),
+                    (Some(4), Some(access)) => format!("T05awatch:{:x};", access.adr),
+                    (Some(0), None) => String::from("T05swbreak:;"),
+                    (Some(1), None) => String::from("T05hwbreak:;"),
+                    _ => String::from("S05"),
+                }
+            }
+            HaltReason::StartOfHistory => String::from("T05replaylog:begin;"),
+            HaltReason::Error(err) => {
+                // Report the error on the gdb console before stopping:
+                let msg = format!("Simulation error: {err}\n");
+                let msg: String = msg.bytes().map(|b| format!("{b:02x}")).collect();
+                self.send_packet(&format!("O{msg}"))?;
+                String::from("S0b")
+            }
+            _ => String::from("S05"),
+        })
+    }
+
+    // Resume at the address given with a 's' or 'c' packet, if any.
+    fn resume_adr(&mut self, args: &str) {
+        if let Ok(adr) = u32::from_str_radix(args, 16) {
+            self.sim.set_pc(adr);
+        }
+    }
+
+    fn read_gdb_register(&self, n: u32) -> String {
+        let val = match n {
+            REG_PC => Some(self.sim.pc),
+            _ => gdb_register(n)
+                .ok()
+                .and_then(|reg| self.sim.peek_register(reg)),
+        };
+        match val {
+            Some(val) => format!("{:08x}", val.swap_bytes()),
+            None => String::from("xxxxxxxx"), // Uninitialized.
+        }
+    }
+
+    fn write_gdb_register(&mut self, n: u32, val: u32) {
+        match n {
+            REG_PC => self.sim.set_pc(val),
+            _ => {
+                if let Ok(reg) = gdb_register(n) {
+                    self.sim.write_register(reg, val);
+                }
+            }
+        }
+    }
+
+    // ==== Packet Transport ====
+
+    fn read_byte(&mut self) -> Result<Option<u8>, anyhow::Error> {
+        if let Some(b) = self.pending.pop_front() {
+            return Ok(Some(b));
+        }
+        let mut buf = [0u8; 1];
+        match self.stream.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    // Receive the next packet. None if gdb disconnected.
+    fn recv_packet(&mut self) -> Result<Option<String>, anyhow::Error> {
+        loop {
+            // Skip acknowledgements and interrupt requests while halted:
+            loop {
+                match self.read_byte()? {
+                    Some(b'$') => break,
+                    Some(_) => (),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(None),
+                }
+            }
+
+            let (Some(hi), Some(lo)) = (self.read_byte()?, self.read_byte()?) else {
+                return Err(anyhow!("gdb disconnected within a packet."));
+            };
+            let checksum = [hi, lo];
+            let checksum = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+
+            if checksum != Some(packet_checksum(&data)) && !self.no_ack {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+
+            if !self.no_ack {
+                self.stream.write_all(b"+")?;
+            }
+
+            let data = unescape(&data);
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<(), anyhow::Error> {
+        let data = escape(data.as_bytes());
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", packet_checksum(&data)).as_bytes());
+
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                Some(b'+') => return Ok(()),
+                Some(b'-') => continue,
+                Some(b) => return Err(anyhow!("Expected packet acknowledgement, got 0x{b:02x}.")),
+                None => return Err(anyhow!("gdb disconnected.")),
+            }
+        }
+    }
+}
+
+impl Drop for GdbServer<'_> {
+    fn drop(&mut self) {
+        for bp in self.breakpoints.drain(..) {
+            self.sim.remove_breakpoint(bp.id);
+        }
+    }
+}
+
+// ==== Helpers ====================================================================================
+
+fn gdb_register(n: u32) -> Result<Register, anyhow::Error> {
+    match n {
+        0..=15 => Register::new(n),
+        REG_XMPC => Ok(Register::Xmpc),
+        REG_XDPC => Ok(Register::Xdpc),
+        _ => Err(anyhow!("Unknown gdb register {n}.")),
+    }
+}
+
+// Check for an interrupt request (0x03) without blocking. Any other bytes received, such as
+// acknowledgements or packets, are kept in pending.
+fn interrupt_requested(
+    mut stream: &TcpStream,
+    pending: &mut VecDeque<u8>,
+) -> Result<bool, anyhow::Error> {
+    let mut buf = [0u8; 64];
+    let mut interrupt = false;
+    stream.set_nonblocking(true)?;
+    let result = loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break Err(anyhow!("gdb disconnected.")),
+            Ok(len) => {
+                for b in &buf[..len] {
+                    match b {
+                        0x03 => interrupt = true,
+                        _ => pending.push_back(*b),
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break Ok(interrupt),
+            Err(err) => break Err(err.into()),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = vec![];
+    for b in data {
+        match b {
+            b'$' | b'#' | b'}' | b'*' => escaped.extend_from_slice(&[b'}', b ^ 0x20]),
+            _ => escaped.push(*b),
+        }
+    }
+    escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut unescaped = vec![];
+    let mut iter = data.iter();
+    while let Some(b) = iter.next() {
+        match b {
+            b'}' => {
+                if let Some(b) = iter.next() {
+                    unescaped.push(b ^ 0x20);
+                }
+            }
+            _ => unescaped.push(*b),
+        }
+    }
+    unescaped
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+// Register values are transferred as little-endian hex strings:
+fn parse_le_word(hex: &str) -> Option<u32> {
+    let bytes = parse_hex(hex)?;
+    let bytes: [u8; 4] = bytes.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+// Parse an 'adr,len' pair:
+fn parse_adr_len(args: &str) -> Option<(u32, u32)> {
+    let (adr, len) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(adr, 16).ok()?,
+        u32::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+// ==== GDB Server Tests ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use crate::{gdb::*, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator(insts: Vec<u32>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Zero,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    // Minimal scripted RSP client:
+    struct Client {
+        stream: TcpStream,
+        no_ack: bool,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let checksum = packet_checksum(data.as_bytes());
+            write!(self.stream, "${data}#{checksum:02x}").unwrap();
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+
+            let mut byte = [0u8; 1];
+            if !self.no_ack {
+                self.stream.read_exact(&mut byte).unwrap();
+                assert_eq!(byte[0], b'+');
+            }
+
+            // Skip console output packets:
+            loop {
+                let response = self.recv();
+                if !response.starts_with('O') || response == "OK" {
+                    return response;
+                }
+            }
+        }
+
+        fn recv(&mut self) -> String {
+            let mut byte = [0u8; 1];
+            self.stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'$');
+
+            let mut data = vec![];
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16);
+            assert_eq!(checksum.unwrap(), packet_checksum(&data));
+
+            if !self.no_ack {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(unescape(&data)).unwrap()
+        }
+    }
+
+    // Serve the simulator, while running the given client script on a separate thread:
+    fn with_client(sim: &mut DRVSim, script: impl FnOnce(&mut Client) + Send + 'static) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let adr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(adr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut client = Client {
+                stream,
+                no_ack: false,
+            };
+            script(&mut client);
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        GdbServer::new(sim, stream).serve().unwrap();
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn listen_on_any_port() {
+        let mut sim = new_simulator(vec![]);
+        let (adr_tx, adr_rx) = std::sync::mpsc::channel();
+
+        let client = thread::spawn(move || {
+            let mut client = Client {
+                stream: TcpStream::connect(adr_rx.recv().unwrap()).unwrap(),
+                no_ack: false,
+            };
+            assert_eq!(client.request("?"), "S05");
+            client.send("k");
+        });
+
+        listen(&mut sim, "127.0.0.1:0", |adr| adr_tx.send(adr).unwrap()).unwrap();
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn helpers() {
+        assert_eq!(escape(b"a$b#c}d"), b"a}\x04b}\x03c}]d");
+        assert_eq!(unescape(&escape(b"a$b#c}d*")), b"a$b#c}d*");
+        assert_eq!(parse_le_word("78563412"), Some(0x12345678));
+        assert_eq!(parse_le_word("785634"), None);
+        assert_eq!(parse_adr_len("1000000,4"), Some((0x1000000, 4)));
+        assert_eq!(parse_hex("00ff1"), None);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+        ]);
+
+        with_client(&mut sim, |client| {
+            let supported = client.request("qSupported:multiprocess+;swbreak+");
+            assert!(supported.contains("qXfer:features:read+"));
+            assert_eq!(client.request("QStartNoAckMode"), "OK");
+            client.no_ack = true;
+
+            assert_eq!(client.request("?"), "S05");
+
+            let xml = client.request("qXfer:features:read:target.xml:0,1000");
+            assert!(xml.starts_with("l<?xml"));
+            assert!(xml.contains(r#"<reg name="xdpc""#));
+            let xml = client.request("qXfer:features:read:target.xml:0,10");
+            assert_eq!(xml.len(), 0x11);
+            assert!(xml.starts_with('m'));
+
+            // Registers are uninitialized, except for x0 and pc:
+            let regs = client.request("g");
+            assert_eq!(regs.len(), 19 * 8);
+            assert_eq!(&regs[0..16], "00000000xxxxxxxx");
+            assert_eq!(&regs[128..136], "00000001");
+
+            assert_eq!(client.request("P1=78563412"), "OK");
+            assert_eq!(client.request("p1"), "78563412");
+            assert_eq!(client.request("P11=04000001"), "OK");
+            assert_eq!(client.request("p11"), "04000001");
+            assert_eq!(client.request("p13"), "E01");
+
+            assert_eq!(client.request("m1000000,4"), "93801000");
+            assert_eq!(client.request("m1000002,4"), "1000");
+            assert_eq!(client.request("m1000004,4"), "E01");
+            assert_eq!(client.request("m1000000,0"), "");
+            assert_eq!(client.request("m0,4"), "E01");
+            assert_eq!(client.request("M2000000,2:beef"), "OK");
+            // Reads do not initialize memory:
+            assert_eq!(client.request("m2000000,3"), "beef");
+            assert_eq!(client.request("M0,1:00"), "E01");
+
+            client.send("k");
+        });
+
+        assert_eq!(sim.read_w(RAM_START).unwrap().val, 0xefbe);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 0x12345678);
+        assert_eq!(sim.read_register(Register::Xmpc).unwrap().val, 0x01000004);
+    }
+
+    #[test]
+    fn step_continue_and_breakpoints() {
+        let mut sim = new_simulator(vec![
+            0x02000137, // LUI x2, 0x2000
+            0x00000093, // ADDI x1, x0, 0
+            0x00108093, // ADDI x1, x1, 1
+            0x00112023, // SW x1, 0x0(x2)
+            0x00410113, // ADDI x2, x2, 4
+            0xff5ff06f, // JAL x0, .-12
+        ]);
+
+        with_client(&mut sim, |client| {
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("p10"), "08000001");
+
+            // Software breakpoint:
+            assert_eq!(client.request("Z0,100000c,4"), "OK");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p10"), "0c000001");
+            assert_eq!(client.request("p1"), "01000000");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p1"), "02000000");
+            assert_eq!(client.request("z0,100000c,4"), "OK");
+
+            // Write watchpoint:
+            assert_eq!(client.request("Z2,2000010,4"), "OK");
+            assert_eq!(client.request("c"), "T05watch:2000010;");
+            assert_eq!(client.request("p1"), "05000000");
+            assert_eq!(client.request("z2,2000010,4"), "OK");
+
+            // Resume at an address:
+            assert_eq!(client.request("Z1,1000008,4"), "OK");
+            assert_eq!(client.request("c1000004"), "T05hwbreak:;");
+            assert_eq!(client.request("p1"), "00000000");
+
+            client.request("D");
+        });
+
+        // Breakpoints set by gdb are removed after it detaches:
+        assert!(matches!(sim.run(100), run::HaltReason::StepLimit));
+    }
+
+    #[test]
+    fn continue_interrupt() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0xffdff06f, // JAL x0, .-4
+        ]);
+        sim.write_register(Register::X1, 0);
+
+        with_client(&mut sim, |client| {
+            assert_eq!(client.request("QStartNoAckMode"), "OK");
+            client.no_ack = true;
+            client.send("c");
+
+            // Packets sent while running are handled after the interrupt:
+            client.stream.write_all(b"$?#3f\x03").unwrap();
+            assert_eq!(client.recv(), "S02");
+            assert_eq!(client.recv(), "S05");
+            assert_ne!(client.request("p1"), "00000000");
+            client.send("k");
+        });
+    }
+
+    #[test]
+    fn reverse_execution() {
+        let mut sim = new_simulator(vec![
+            0x00000093, // ADDI x1, x0, 0
+            0x00108093, // ADDI x1, x1, 1
+            0xffdff06f, // JAL x0, .-4
+        ]);
+        sim.set_undo_depth(100);
+
+        with_client(&mut sim, |client| {
+            assert!(client.request("qSupported").contains("ReverseContinue+"));
+            assert_eq!(client.request("Z0,1000004,4"), "OK");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("c"), "T05swbreak:;");
+            assert_eq!(client.request("p1"), "01000000");
+
+            assert_eq!(client.request("bs"), "S05");
+            assert_eq!(client.request("p10"), "08000001");
+            assert_eq!(client.request("bc"), "T05swbreak:;");
+            assert_eq!(client.request("p10"), "04000001");
+            assert_eq!(client.request("p1"), "00000000");
+            assert_eq!(client.request("bc"), "T05replaylog:begin;");
+            assert_eq!(client.request("p10"), "00000001");
+            assert_eq!(client.request("p1"), "xxxxxxxx");
+            client.send("k");
+        });
+    }
+
+    #[test]
+    fn breakpoints_removed_on_error() {
+        let mut sim = new_simulator(vec![
+            0x00000013, // ADDI x0, x0, 0
+        ]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let adr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut client = Client {
+                stream: TcpStream::connect(adr).unwrap(),
+                no_ack: false,
+            };
+            assert_eq!(client.request("Z0,1000000,4"), "OK");
+            // Disconnect within a packet:
+            client.stream.write_all(b"$?#").unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        assert!(GdbServer::new(&mut sim, stream).serve().is_err());
+        client.join().unwrap();
+        let reason = sim.run(1);
+        assert!(matches!(reason, run::HaltReason::StepLimit), "{reason:?}");
+    }
+
+    #[test]
+    fn simulation_error() {
+        let mut sim = new_simulator(vec![
+            0x00000013, // ADDI x0, x0, 0
+        ]);
+
+        with_client(&mut sim, |client| {
+            // Falls through into uninitialized memory:
+            assert_eq!(client.request("c"), "S0b");
+            client.send("k");
+        });
+    }
+}
@@ -218,6 +218,38 @@ pub enum Instruction {
 
     DRET,
     MRET,
+
+    // Zicsr:
+    CSRRW {
+        csr: u32,
+        rs1: Register,
+        rd: Register,
+    },
+    CSRRS {
+        csr: u32,
+        rs1: Register,
+        rd: Register,
+    },
+    CSRRC {
+        csr: u32,
+        rs1: Register,
+        rd: Register,
+    },
+    CSRRWI {
+        csr: u32,
+        uimm: u32,
+        rd: Register,
+    },
+    CSRRSI {
+        csr: u32,
+        uimm: u32,
+        rd: Register,
+    },
+    CSRRCI {
+        csr: u32,
+        uimm: u32,
+        rd: Register,
+    },
 }
 
 // ==== Instruction-to-String formatting ===========================================================
@@ -267,6 +299,18 @@ impl std::fmt::Display for Instruction {
             Instruction::EBREAK => write!(f, "ebreak"),
             Instruction::DRET => write!(f, "dret"),
             Instruction::MRET => write!(f, "mret"),
+            Instruction::CSRRW { csr, rs1, rd } => write!(f, "csrrw {rd:?}, 0x{csr:x}, {rs1:?}"),
+            Instruction::CSRRS { csr, rs1, rd } => write!(f, "csrrs {rd:?}, 0x{csr:x}, {rs1:?}"),
+            Instruction::CSRRC { csr, rs1, rd } => write!(f, "csrrc {rd:?}, 0x{csr:x}, {rs1:?}"),
+            Instruction::CSRRWI { csr, uimm, rd } => {
+                write!(f, "csrrwi {rd:?}, 0x{csr:x}, 0x{uimm:x}")
+            }
+            Instruction::CSRRSI { csr, uimm, rd } => {
+                write!(f, "csrrsi {rd:?}, 0x{csr:x}, 0x{uimm:x}")
+            }
+            Instruction::CSRRCI { csr, uimm, rd } => {
+                write!(f, "csrrci {rd:?}, 0x{csr:x}, 0x{uimm:x}")
+            }
         }
     }
 }
@@ -392,13 +392,51 @@ pub fn decode_inst(inst: u32) -> Result<Instruction, anyhow::Error> {
         }
 
         0b1110011 => {
-            // ECALL, EBREAK, MRET, DRET
-            match inst {
-                0x00000073 => Ok(Instruction::ECALL),
-                0x00100073 => Ok(Instruction::EBREAK),
-                0x7b200073 => Ok(Instruction::DRET),
-                0x30200073 => Ok(Instruction::MRET),
-                _ => Err(anyhow!("Invalid ECALL/EBREAK/MRET/DRET-style instruction.")),
+            // ECALL, EBREAK, MRET, DRET, CSRRW, CSRRS, CSRRC, CSRRWI, CSRRSI, CSRRCI
+            let funct3 = (inst >> 12) & 0b111;
+            let csr = (inst >> 20) & 0xFFF;
+            let uimm = (inst >> 15) & 0b11111;
+            let rd = (inst >> 7) & 0b11111;
+
+            match funct3 {
+                0b000 => match inst {
+                    0x00000073 => Ok(Instruction::ECALL),
+                    0x00100073 => Ok(Instruction::EBREAK),
+                    0x7b200073 => Ok(Instruction::DRET),
+                    0x30200073 => Ok(Instruction::MRET),
+                    _ => Err(anyhow!("Invalid ECALL/EBREAK/MRET/DRET-style instruction.")),
+                },
+                0b001 => Ok(Instruction::CSRRW {
+                    csr,
+                    rs1: Register::new(uimm)?,
+                    rd: Register::new(rd)?,
+                }),
+                0b010 => Ok(Instruction::CSRRS {
+                    csr,
+                    rs1: Register::new(uimm)?,
+                    rd: Register::new(rd)?,
+                }),
+                0b011 => Ok(Instruction::CSRRC {
+                    csr,
+                    rs1: Register::new(uimm)?,
+                    rd: Register::new(rd)?,
+                }),
+                0b101 => Ok(Instruction::CSRRWI {
+                    csr,
+                    uimm,
+                    rd: Register::new(rd)?,
+                }),
+                0b110 => Ok(Instruction::CSRRSI {
+                    csr,
+                    uimm,
+                    rd: Register::new(rd)?,
+                }),
+                0b111 => Ok(Instruction::CSRRCI {
+                    csr,
+                    uimm,
+                    rd: Register::new(rd)?,
+                }),
+                n => Err(anyhow!("Unknown funct3 for system instructions 0b{n:b}")),
             }
         }
 
@@ -456,6 +494,12 @@ mod tests {
             (0x00100073_u32, "ebreak"),
             (0x7b200073_u32, "dret"),
             (0x30200073_u32, "mret"),
+            (0x305110f3_u32, "csrrw x1, 0x305, x2"),
+            (0x342021f3_u32, "csrrs x3, 0x342, x0"),
+            (0x3002b273_u32, "csrrc x4, 0x300, x5"),
+            (0xb0045373_u32, "csrrwi x6, 0xb00, 0x8"),
+            (0x3001e073_u32, "csrrsi x0, 0x300, 0x3"),
+            (0x30047073_u32, "csrrci x0, 0x300, 0x8"),
         ];
 
         for (binary, orig) in input {
@@ -0,0 +1,426 @@
+use crate::inst::{Instruction, Register};
+use anyhow::anyhow;
+
+// ==== Immediate Checks ===========================================================================
+
+// Check that a sign-extended immediate fits into a signed field of the given width:
+fn check_signed(imm: u32, bits: u32, name: &str) -> Result<(), anyhow::Error> {
+    let val = imm as i32;
+    let min = -(1i32 << (bits - 1));
+    let max = (1i32 << (bits - 1)) - 1;
+    if val < min || val > max {
+        return Err(anyhow!(
+            "{name} immediate {val} does not fit into {bits} signed bits."
+        ));
+    }
+    Ok(())
+}
+
+fn check_unsigned(val: u32, bits: u32, name: &str) -> Result<(), anyhow::Error> {
+    if val >> bits != 0 {
+        return Err(anyhow!(
+            "{name} 0x{val:x} does not fit into {bits} unsigned bits."
+        ));
+    }
+    Ok(())
+}
+
+// ==== Base Instruction Format Encoding ===========================================================
+
+fn reg(reg: Register) -> u32 {
+    reg as u32
+}
+
+fn encode_r(funct7: u32, rs2: Register, rs1: Register, funct3: u32, rd: Register, op: u32) -> u32 {
+    (funct7 << 25) | (reg(rs2) << 20) | (reg(rs1) << 15) | (funct3 << 12) | (reg(rd) << 7) | op
+}
+
+fn encode_i(
+    imm: u32,
+    rs1: Register,
+    funct3: u32,
+    rd: Register,
+    op: u32,
+) -> Result<u32, anyhow::Error> {
+    check_signed(imm, 12, "I-type")?;
+    Ok(((imm & 0xFFF) << 20) | (reg(rs1) << 15) | (funct3 << 12) | (reg(rd) << 7) | op)
+}
+
+// Shift-by-immediate instructions are I-type, with the shift amount in the lower 5 bits and
+// the upper 7 bits selecting the shift type:
+fn encode_shift(
+    ctrl: u32,
+    shamt: u32,
+    rs1: Register,
+    funct3: u32,
+    rd: Register,
+) -> Result<u32, anyhow::Error> {
+    check_unsigned(shamt, 5, "Shift amount")?;
+    Ok((ctrl << 25)
+        | (shamt << 20)
+        | (reg(rs1) << 15)
+        | (funct3 << 12)
+        | (reg(rd) << 7)
+        | 0b0010011)
+}
+
+fn encode_s(imm: u32, rs2: Register, rs1: Register, funct3: u32) -> Result<u32, anyhow::Error> {
+    check_signed(imm, 12, "S-type")?;
+    Ok((((imm >> 5) & 0x7F) << 25)
+        | (reg(rs2) << 20)
+        | (reg(rs1) << 15)
+        | (funct3 << 12)
+        | ((imm & 0x1F) << 7)
+        | 0b0100011)
+}
+
+fn encode_b(imm: u32, rs2: Register, rs1: Register, funct3: u32) -> Result<u32, anyhow::Error> {
+    check_signed(imm, 13, "B-type")?;
+    if imm & 0x1 != 0 {
+        return Err(anyhow!(
+            "B-type immediate 0x{imm:x} is not a multiple of 2."
+        ));
+    }
+    Ok((((imm >> 12) & 0x1) << 31)
+        | (((imm >> 5) & 0x3F) << 25)
+        | (reg(rs2) << 20)
+        | (reg(rs1) << 15)
+        | (funct3 << 12)
+        | (((imm >> 1) & 0xF) << 8)
+        | (((imm >> 11) & 0x1) << 7)
+        | 0b1100011)
+}
+
+fn encode_u(imm: u32, rd: Register, op: u32) -> Result<u32, anyhow::Error> {
+    if imm & 0xFFF != 0 {
+        return Err(anyhow!(
+            "U-type immediate 0x{imm:x} has non-zero lower 12 bits."
+        ));
+    }
+    Ok(imm | (reg(rd) << 7) | op)
+}
+
+fn encode_j(imm: u32, rd: Register) -> Result<u32, anyhow::Error> {
+    check_signed(imm, 21, "J-type")?;
+    if imm & 0x1 != 0 {
+        return Err(anyhow!(
+            "J-type immediate 0x{imm:x} is not a multiple of 2."
+        ));
+    }
+    Ok((((imm >> 20) & 0x1) << 31)
+        | (((imm >> 1) & 0x3FF) << 21)
+        | (((imm >> 11) & 0x1) << 20)
+        | (((imm >> 12) & 0xFF) << 12)
+        | (reg(rd) << 7)
+        | 0b1101111)
+}
+
+fn encode_csr(csr: u32, src: u32, funct3: u32, rd: Register) -> Result<u32, anyhow::Error> {
+    check_unsigned(csr, 12, "CSR address")?;
+    Ok((csr << 20) | (src << 15) | (funct3 << 12) | (reg(rd) << 7) | 0b1110011)
+}
+
+// ==== Instruction Encoding =======================================================================
+
+impl Instruction {
+    // Encode an instruction, failing if an immediate cannot be represented. Immediates are
+    // expected sign-extended, as produced by decode_inst.
+    pub fn encode(&self) -> Result<u32, anyhow::Error> {
+        match *self {
+            // RV32I:
+            Instruction::LUI { imm, rd } => encode_u(imm, rd, 0b0110111),
+            Instruction::AUIPC { imm, rd } => encode_u(imm, rd, 0b0010111),
+            Instruction::JAL { imm, rd } => encode_j(imm, rd),
+            Instruction::JALR { imm, rs1, rd } => encode_i(imm, rs1, 0b000, rd, 0b1100111),
+            Instruction::BEQ { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b000),
+            Instruction::BNE { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b001),
+            Instruction::BLT { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b100),
+            Instruction::BGE { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b101),
+            Instruction::BLTU { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b110),
+            Instruction::BGEU { imm, rs2, rs1 } => encode_b(imm, rs2, rs1, 0b111),
+            Instruction::LB { imm, rs1, rd } => encode_i(imm, rs1, 0b000, rd, 0b0000011),
+            Instruction::LH { imm, rs1, rd } => encode_i(imm, rs1, 0b001, rd, 0b0000011),
+            Instruction::LW { imm, rs1, rd } => encode_i(imm, rs1, 0b010, rd, 0b0000011),
+            Instruction::LBU { imm, rs1, rd } => encode_i(imm, rs1, 0b100, rd, 0b0000011),
+            Instruction::LHU { imm, rs1, rd } => encode_i(imm, rs1, 0b101, rd, 0b0000011),
+            Instruction::SB { imm, rs2, rs1 } => encode_s(imm, rs2, rs1, 0b000),
+            Instruction::SH { imm, rs2, rs1 } => encode_s(imm, rs2, rs1, 0b001),
+            Instruction::SW { imm, rs2, rs1 } => encode_s(imm, rs2, rs1, 0b010),
+            Instruction::ADDI { imm, rs1, rd } => encode_i(imm, rs1, 0b000, rd, 0b0010011),
+            Instruction::SLTI { imm, rs1, rd } => encode_i(imm, rs1, 0b010, rd, 0b0010011),
+            Instruction::SLTIU { imm, rs1, rd } => encode_i(imm, rs1, 0b011, rd, 0b0010011),
+            Instruction::XORI { imm, rs1, rd } => encode_i(imm, rs1, 0b100, rd, 0b0010011),
+            Instruction::ORI { imm, rs1, rd } => encode_i(imm, rs1, 0b110, rd, 0b0010011),
+            Instruction::ANDI { imm, rs1, rd } => encode_i(imm, rs1, 0b111, rd, 0b0010011),
+            Instruction::SLLI { shamt, rs1, rd } => encode_shift(0b0000000, shamt, rs1, 0b001, rd),
+            Instruction::SRLI { shamt, rs1, rd } => encode_shift(0b0000000, shamt, rs1, 0b101, rd),
+            Instruction::SRAI { shamt, rs1, rd } => encode_shift(0b0100000, shamt, rs1, 0b101, rd),
+            Instruction::ADD { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b000, rd, 0b0110011)),
+            Instruction::SUB { rs2, rs1, rd } => {
+                Ok(encode_r(0b0100000, rs2, rs1, 0b000, rd, 0b0110011))
+            }
+            Instruction::SLL { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b001, rd, 0b0110011)),
+            Instruction::SLT { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b010, rd, 0b0110011)),
+            Instruction::SLTU { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b011, rd, 0b0110011)),
+            Instruction::XOR { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b100, rd, 0b0110011)),
+            Instruction::SRL { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b101, rd, 0b0110011)),
+            Instruction::SRA { rs2, rs1, rd } => {
+                Ok(encode_r(0b0100000, rs2, rs1, 0b101, rd, 0b0110011))
+            }
+            Instruction::OR { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b110, rd, 0b0110011)),
+            Instruction::AND { rs2, rs1, rd } => Ok(encode_r(0, rs2, rs1, 0b111, rd, 0b0110011)),
+            Instruction::FENCE { fm, pred, succ } => {
+                check_unsigned(fm, 4, "FENCE mode")?;
+                check_unsigned(pred, 4, "FENCE predecessor set")?;
+                check_unsigned(succ, 4, "FENCE successor set")?;
+                Ok((fm << 28) | (pred << 24) | (succ << 20) | 0b0001111)
+            }
+            Instruction::ECALL => Ok(0x00000073),
+            Instruction::EBREAK => Ok(0x00100073),
+
+            Instruction::DRET => Ok(0x7b200073),
+            Instruction::MRET => Ok(0x30200073),
+
+            // Zicsr:
+            Instruction::CSRRW { csr, rs1, rd } => encode_csr(csr, reg(rs1), 0b001, rd),
+            Instruction::CSRRS { csr, rs1, rd } => encode_csr(csr, reg(rs1), 0b010, rd),
+            Instruction::CSRRC { csr, rs1, rd } => encode_csr(csr, reg(rs1), 0b011, rd),
+            Instruction::CSRRWI { csr, uimm, rd } => {
+                check_unsigned(uimm, 5, "CSR immediate")?;
+                encode_csr(csr, uimm, 0b101, rd)
+            }
+            Instruction::CSRRSI { csr, uimm, rd } => {
+                check_unsigned(uimm, 5, "CSR immediate")?;
+                encode_csr(csr, uimm, 0b110, rd)
+            }
+            Instruction::CSRRCI { csr, uimm, rd } => {
+                check_unsigned(uimm, 5, "CSR immediate")?;
+                encode_csr(csr, uimm, 0b111, rd)
+            }
+        }
+    }
+}
+
+// ==== Instruction Encoding Tests =================================================================
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        inst::{Instruction, Register},
+        inst_decoding::decode_inst,
+    };
+
+    fn regs() -> impl DoubleEndedIterator<Item = Register> {
+        (0..18).map(|idx| Register::new(idx).unwrap())
+    }
+
+    // Sign-extended immediates at and around the limits of a signed field, in steps of align:
+    fn signed_imms(bits: u32, align: u32) -> Vec<u32> {
+        let min = -(1i32 << (bits - 1));
+        let max = (1i32 << (bits - 1)) - align as i32;
+        let mut imms = vec![
+            min,
+            min + align as i32,
+            -(align as i32),
+            0,
+            align as i32,
+            max,
+        ];
+        imms.extend(
+            (0..bits - 1)
+                .map(|bit| 1i32 << bit)
+                .filter(|imm| imm % align as i32 == 0),
+        );
+        imms.into_iter().map(|imm| imm as u32).collect()
+    }
+
+    // Every instruction variant, for all registers and boundary immediates:
+    fn all_instructions() -> Vec<Instruction> {
+        let mut insts = vec![
+            Instruction::ECALL,
+            Instruction::EBREAK,
+            Instruction::DRET,
+            Instruction::MRET,
+        ];
+        for rd in regs() {
+            for imm in [0, 0x1000, 0x7FFFF000, 0x80000000, 0xFFFFF000] {
+                insts.push(Instruction::LUI { imm, rd });
+                insts.push(Instruction::AUIPC { imm, rd });
+            }
+            for imm in signed_imms(21, 2) {
+                insts.push(Instruction::JAL { imm, rd });
+            }
+        }
+        for (rs1, rs2) in regs().zip(regs().rev()) {
+            for imm in signed_imms(13, 2) {
+                insts.push(Instruction::BEQ { imm, rs2, rs1 });
+                insts.push(Instruction::BNE { imm, rs2, rs1 });
+                insts.push(Instruction::BLT { imm, rs2, rs1 });
+                insts.push(Instruction::BGE { imm, rs2, rs1 });
+                insts.push(Instruction::BLTU { imm, rs2, rs1 });
+                insts.push(Instruction::BGEU { imm, rs2, rs1 });
+            }
+            for imm in signed_imms(12, 1) {
+                insts.push(Instruction::SB { imm, rs2, rs1 });
+                insts.push(Instruction::SH { imm, rs2, rs1 });
+                insts.push(Instruction::SW { imm, rs2, rs1 });
+            }
+        }
+        for (rs1, rd) in regs().zip(regs().rev()) {
+            for imm in signed_imms(12, 1) {
+                insts.push(Instruction::JALR { imm, rs1, rd });
+                insts.push(Instruction::LB { imm, rs1, rd });
+                insts.push(Instruction::LH { imm, rs1, rd });
+                insts.push(Instruction::LW { imm, rs1, rd });
+                insts.push(Instruction::LBU { imm, rs1, rd });
+                insts.push(Instruction::LHU { imm, rs1, rd });
+                insts.push(Instruction::ADDI { imm, rs1, rd });
+                insts.push(Instruction::SLTI { imm, rs1, rd });
+                insts.push(Instruction::SLTIU { imm, rs1, rd });
+                insts.push(Instruction::XORI { imm, rs1, rd });
+                insts.push(Instruction::ORI { imm, rs1, rd });
+                insts.push(Instruction::ANDI { imm, rs1, rd });
+            }
+            for shamt in 0..32 {
+                insts.push(Instruction::SLLI { shamt, rs1, rd });
+                insts.push(Instruction::SRLI { shamt, rs1, rd });
+                insts.push(Instruction::SRAI { shamt, rs1, rd });
+            }
+            for csr in [0x000, 0x300, 0x7b2, 0xb00, 0xfff] {
+                insts.push(Instruction::CSRRW { csr, rs1, rd });
+                insts.push(Instruction::CSRRS { csr, rs1, rd });
+                insts.push(Instruction::CSRRC { csr, rs1, rd });
+                let uimm = rs1 as u32 * 31 / 17;
+                insts.push(Instruction::CSRRWI { csr, uimm, rd });
+                insts.push(Instruction::CSRRSI { csr, uimm, rd });
+                insts.push(Instruction::CSRRCI { csr, uimm, rd });
+            }
+        }
+        for rs1 in regs() {
+            for rd in regs() {
+                for rs2 in regs() {
+                    insts.push(Instruction::ADD { rs2, rs1, rd });
+                    insts.push(Instruction::SUB { rs2, rs1, rd });
+                    insts.push(Instruction::SLL { rs2, rs1, rd });
+                    insts.push(Instruction::SLT { rs2, rs1, rd });
+                    insts.push(Instruction::SLTU { rs2, rs1, rd });
+                    insts.push(Instruction::XOR { rs2, rs1, rd });
+                    insts.push(Instruction::SRL { rs2, rs1, rd });
+                    insts.push(Instruction::SRA { rs2, rs1, rd });
+                    insts.push(Instruction::OR { rs2, rs1, rd });
+                    insts.push(Instruction::AND { rs2, rs1, rd });
+                }
+            }
+        }
+        for fm in 0..16 {
+            for set in 0..16 {
+                insts.push(Instruction::FENCE {
+                    fm,
+                    pred: set,
+                    succ: 15 - set,
+                });
+            }
+        }
+        insts
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for inst in all_instructions() {
+            let word = inst.encode().unwrap();
+            assert_eq!(decode_inst(word).unwrap(), inst, "0x{word:08x}");
+        }
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        // All decodable words re-encode to themselves, except for the ignored rs1/rd fields of
+        // FENCE:
+        let mut rng = StdRng::seed_from_u64(0xD2F);
+        let mut decoded = 0;
+        for _ in 0..50000 {
+            let opcode = [
+                0b0110111, 0b0010111, 0b1101111, 0b1100111, 0b1100011, 0b0000011, 0b0100011,
+                0b0010011, 0b0110011, 0b0001111, 0b1110011,
+            ][rng.gen_range(0..11)];
+            let word = (rng.gen::<u32>() & !0x7F) | opcode;
+            let Ok(inst) = decode_inst(word) else {
+                continue;
+            };
+            decoded += 1;
+            let expected = match inst {
+                Instruction::FENCE { .. } => word & 0xFFF0707F,
+                _ => word,
+            };
+            assert_eq!(inst.encode().unwrap(), expected, "{inst}");
+        }
+        assert!(decoded > 2500);
+    }
+
+    #[test]
+    fn encode_invalid() {
+        let (rs1, rd) = (Register::X1, Register::X2);
+        let invalid = [
+            Instruction::LUI { imm: 0x1001, rd },
+            Instruction::JAL { imm: 0x100000, rd },
+            Instruction::JAL { imm: 0x3, rd },
+            Instruction::JAL {
+                imm: (-0x100002i32) as u32,
+                rd,
+            },
+            Instruction::BEQ {
+                imm: 0x1000,
+                rs2: rd,
+                rs1,
+            },
+            Instruction::BNE {
+                imm: 0x7,
+                rs2: rd,
+                rs1,
+            },
+            Instruction::ADDI {
+                imm: 0x800,
+                rs1,
+                rd,
+            },
+            Instruction::LW {
+                imm: (-0x801i32) as u32,
+                rs1,
+                rd,
+            },
+            Instruction::SW {
+                imm: 0xFFF,
+                rs2: rd,
+                rs1,
+            },
+            Instruction::SLLI { shamt: 32, rs1, rd },
+            Instruction::CSRRW {
+                csr: 0x1000,
+                rs1,
+                rd,
+            },
+            Instruction::CSRRSI {
+                csr: 0x300,
+                uimm: 32,
+                rd,
+            },
+            Instruction::FENCE {
+                fm: 16,
+                pred: 0,
+                succ: 0,
+            },
+        ];
+        for inst in invalid {
+            assert!(inst.encode().is_err(), "{inst}");
+        }
+
+        // Limits are encodable:
+        let imm = (-0x800i32) as u32;
+        assert_eq!(
+            Instruction::ADDI { imm, rs1, rd }.encode().unwrap(),
+            0x80008113
+        );
+    }
+}
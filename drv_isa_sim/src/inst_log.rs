@@ -1,4 +1,9 @@
-use crate::inst::{Instruction, Register};
+use crate::{
+    csr::csr_name,
+    inst::{Instruction, Register},
+    symbols::SymbolTable,
+    trap::Trap,
+};
 
 // ==== Type Definitions ===========================================================================
 
@@ -6,20 +11,23 @@ use crate::inst::{Instruction, Register};
 pub enum ValueOrigin {
     Register(Register),
     Memory { adr: u32, bytes: u32 },
+    Csr(u32),
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Value {
     pub origin: ValueOrigin,
     pub val: u32,
+    pub prev: Option<u32>, // Value before a commit. None for inputs, or if uninitialized.
 }
 
 #[derive(Debug)]
 pub struct InstLog {
     pub pc: u32,
-    pub inst: Instruction,
+    pub inst: Option<Instruction>, // None if the instruction could not be fetched or decoded.
     pub handling_trap: bool,
     pub branching: Option<u32>,
+    pub trap: Option<Trap>, // Trap taken instead of completing this instruction.
     pub debug_mode: bool,
     pub input_values: Vec<Value>,
     pub commit_values: Vec<Value>,
@@ -32,6 +40,7 @@ impl Value {
         Value {
             origin: ValueOrigin::Register(reg),
             val,
+            prev: None,
         }
     }
 
@@ -39,8 +48,21 @@ impl Value {
         Value {
             origin: ValueOrigin::Memory { adr, bytes },
             val,
+            prev: None,
         }
     }
+
+    pub fn csr_value(csr: u32, val: u32) -> Value {
+        Value {
+            origin: ValueOrigin::Csr(csr),
+            val,
+            prev: None,
+        }
+    }
+
+    pub fn with_prev(self, prev: Option<u32>) -> Value {
+        Value { prev, ..self }
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -57,6 +79,10 @@ impl std::fmt::Display for Value {
                 write!(f, "mem[0x{:08x}] = 0x{:08x}", adr, self.val)
             }
             ValueOrigin::Memory { .. } => panic!(),
+            ValueOrigin::Csr(csr) => match csr_name(csr) {
+                Some(name) => write!(f, "{} = 0x{:08x}", name, self.val),
+                None => write!(f, "csr[0x{:03x}] = 0x{:08x}", csr, self.val),
+            },
         }
     }
 }
@@ -65,8 +91,22 @@ impl std::fmt::Display for Value {
 
 impl InstLog {
     pub fn to_log_string(&self) -> String {
+        self.format_log(None)
+    }
+
+    // Log string with the PC and branch target annotated with the symbols containing them.
+    pub fn to_symbolized_log_string(&self, symbols: &SymbolTable) -> String {
+        self.format_log(Some(symbols))
+    }
+
+    fn format_log(&self, symbols: Option<&SymbolTable>) -> String {
+        let format_adr = |adr: u32| match symbols {
+            Some(symbols) => symbols.format_adr(adr),
+            None => format!("0x{:08x}", adr),
+        };
+
         let mut result = String::new();
-        result.push_str(format!("0x{:08x}: ", self.pc).as_str());
+        result.push_str(format!("{}: ", format_adr(self.pc)).as_str());
         result.push_str(
             format!(
                 "[{}{}] ",
@@ -75,9 +115,16 @@ impl InstLog {
             )
             .as_str(),
         );
-        result.push_str(format!("{:>25} |", self.inst.to_string()).as_str());
+        let inst = match self.inst {
+            Some(inst) => inst.to_string(),
+            None => "???".to_string(),
+        };
+        result.push_str(format!("{:>25} |", inst).as_str());
+        if let Some(trap) = self.trap {
+            result.push_str(format!(" Trap: {}", trap).as_str());
+        }
         if let Some(destination) = self.branching {
-            result.push_str(format!(" Branching: 0x{:08x}", destination).as_str());
+            result.push_str(format!(" Branching: {}", format_adr(destination)).as_str());
         }
         if !self.input_values.is_empty() {
             result.push_str(" Input: [".to_string().as_str());
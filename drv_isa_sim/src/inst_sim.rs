@@ -1,32 +1,127 @@
-use crate::{inst::Instruction, inst_decoding::decode_inst, inst_log::InstLog, DRVSim};
+use anyhow::anyhow;
+
+use crate::{
+    csr::{IllegalCsrAccess, MCYCLE, MCYCLEH, MINSTRET, MINSTRETH},
+    inst::{Instruction, Register},
+    inst_decoding::decode_inst,
+    inst_log::{InstLog, Value, ValueOrigin},
+    trap::{Exception, Trap, TrapCause},
+    AccessFault, DRVSim, FaultMode,
+};
+
+// ==== Type Definitions ===========================================================================
+
+// Reason an instruction could not be completed:
+enum Fault {
+    Trap(Trap),           // Architectural exception or interrupt, handled by the trap handler.
+    Error(anyhow::Error), // Simulation error, stopping the simulation.
+}
+
+impl From<anyhow::Error> for Fault {
+    fn from(err: anyhow::Error) -> Fault {
+        Fault::Error(err)
+    }
+}
 
 // ==== Instruction Implementation =================================================================
 
 impl DRVSim {
     pub fn step(&mut self) -> Result<InstLog, anyhow::Error> {
-        // Fetch & decode instruction:
-        let inst = self.read_w(self.pc)?.val;
-        let inst = decode_inst(inst)?;
+        // Record the state overwritten by this step, so that it can be undone:
+        self.begin_undo_step();
+        self.breakpoints.resume_pc = None;
+        let result = self.step_inst();
+        self.end_undo_step();
+        result
+    }
+
+    fn step_inst(&mut self) -> Result<InstLog, anyhow::Error> {
+        // Enter debug mode if requested, saving the current PC in Xdpc and jumping to the
+        // debug program buffer:
+        let mut dbg_entry = None;
+        if self.dbg_req && !self.debug_mode {
+            self.debug_mode = true;
+            dbg_entry = Some(self.write_register(Register::Xdpc, self.pc));
+            self.pc = self.config.dvec;
+        }
 
-        // Track if the instruction branched and provides the next PC value,
-        // or if the program counter needs to be incremented to the next instruction:
-        let mut branching = None;
+        // Keep track of details for logging, and track all values read and commited by
+        // this instruciton:
+        let mut log = InstLog {
+            pc: self.pc,
+            inst: None,
+            handling_trap: self.handling_trap,
+            branching: None,
+            trap: None,
+            debug_mode: self.debug_mode,
+            input_values: vec![],
+            commit_values: dbg_entry.into_iter().collect(),
+        };
 
-        // Keep track of details for logging:
-        let log_pc = self.pc;
-        let log_handling_trap = false; // TODO
-        let log_debug_mode = false; // TODO
+        // Take a pending interrupt, or fetch, decode and execute the instruction. Enter the
+        // trap handler instead if the instruction raises an exception:
+        let result = match self.pending_interrupt() {
+            Some(line) => Err(Fault::Trap(Trap {
+                cause: TrapCause::Interrupt(line),
+                tval: 0,
+            })),
+            None => self.execute(&mut log),
+        };
 
-        // Track all values read and commited by this instruciton for logging:
-        let mut log_input_values = vec![];
-        let mut log_commit_values = vec![];
+        match result {
+            Ok(()) => (),
+            Err(Fault::Trap(trap)) => {
+                log.commit_values.push(self.enter_trap(trap)?);
+                log.branching = Some(self.csr.mtvec);
+                log.trap = Some(trap);
+            }
+            Err(Fault::Error(err)) => return Err(err),
+        }
+
+        // Advance the PC to the next instruction if the instruction did not branch:
+        if let Some(destination) = log.branching {
+            self.pc = destination;
+        } else {
+            self.pc = u32::wrapping_add(self.pc, 4);
+        }
+
+        self.tick_devices();
+
+        // Update performance counters, unless the instruction wrote them:
+        let wrote_csr = |csrs: [u32; 2]| {
+            log.commit_values
+                .iter()
+                .any(|val| matches!(val.origin, ValueOrigin::Csr(csr) if csrs.contains(&csr)))
+        };
+        if !wrote_csr([MCYCLE, MCYCLEH]) {
+            self.csr.mcycle = u64::wrapping_add(self.csr.mcycle, 1);
+        }
+        if log.trap.is_none() && !wrote_csr([MINSTRET, MINSTRETH]) {
+            self.csr.minstret = u64::wrapping_add(self.csr.minstret, 1);
+        }
+
+        Ok(log)
+    }
+
+    fn execute(&mut self, log: &mut InstLog) -> Result<(), Fault> {
+        // Fetch & decode instruction, unless it is in the predecode cache:
+        let (inst, inst_word) = match self.cached_inst(self.pc) {
+            Some(cached) => cached,
+            None => {
+                let inst_word = self.fetch()?;
+                let inst = self.decode(inst_word)?;
+                self.cache_inst(self.pc, (inst, inst_word));
+                (inst, inst_word)
+            }
+        };
+        log.inst = Some(inst);
 
         // Execute the fetched instruction:
         match inst {
             Instruction::LUI { imm, rd } => {
                 // LUI rd, imm:
                 // Places immediate value in MSBs of rd, leaving the rest zero.
-                log_commit_values.push(self.write_register(rd, imm));
+                log.commit_values.push(self.write_register(rd, imm));
             }
 
             Instruction::AUIPC { imm, rd } => {
@@ -34,7 +129,7 @@ impl DRVSim {
                 // Adds the immediate value to the PC of this instruction, placing the result
                 // in rd while ignoring overflows.
                 let result = u32::wrapping_add(self.pc, imm);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::JAL { imm, rd } => {
@@ -44,10 +139,10 @@ impl DRVSim {
                 // in rd.
                 let pc = self.pc;
 
-                branching = Some(u32::wrapping_add(pc, imm));
+                log.branching = Some(self.check_jump_target(u32::wrapping_add(pc, imm))?);
 
                 let next_inst = u32::wrapping_add(pc, 4);
-                log_commit_values.push(self.write_register(rd, next_inst));
+                log.commit_values.push(self.write_register(rd, next_inst));
             }
 
             Instruction::JALR { imm, rs1, rd } => {
@@ -58,11 +153,12 @@ impl DRVSim {
                 let pc = self.pc;
 
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
-                branching = Some(u32::wrapping_add(inp_rs1.val, imm) & (!0x1));
+                log.input_values.push(inp_rs1);
+                let destination = u32::wrapping_add(inp_rs1.val, imm) & (!0x1);
+                log.branching = Some(self.check_jump_target(destination)?);
 
                 let next_inst = u32::wrapping_add(pc, 4);
-                log_commit_values.push(self.write_register(rd, next_inst));
+                log.commit_values.push(self.write_register(rd, next_inst));
             }
 
             Instruction::BEQ { imm, rs2, rs1 } => {
@@ -71,12 +167,12 @@ impl DRVSim {
                 // instruction and the immediate value, otherwise advance to pc+4
                 // as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if inp_rs1.val == inp_rs2.val {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -86,12 +182,12 @@ impl DRVSim {
                 // instruction and the immediate value, otherwise advance to pc+4
                 // as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if inp_rs1.val != inp_rs2.val {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -101,12 +197,12 @@ impl DRVSim {
                 // If rs1 is strictly less than rs2, branch to the sum of the address of this
                 // instruction and the immediate value, otherwise advance to pc+4 as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if (inp_rs1.val as i32) < (inp_rs2.val as i32) {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -116,12 +212,12 @@ impl DRVSim {
                 // If rs1 is greater or equal to rs2, branch to the sum of the address of this
                 // instruction and the immediate value, otherwise advance to pc+4 as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if (inp_rs1.val as i32) >= (inp_rs2.val as i32) {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -131,12 +227,12 @@ impl DRVSim {
                 // If rs1 is strictly less than rs2, branch to the sum of the address of this
                 // instruction and the immediate value, otherwise advance to pc+4 as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if inp_rs1.val < inp_rs2.val {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -146,12 +242,12 @@ impl DRVSim {
                 // If rs1 is greater or equal to rs2, branch to the sum of the address of this
                 // instruction and the immediate value, otherwise advance to pc+4 as normal.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if inp_rs1.val >= inp_rs2.val {
-                    branching = Some(u32::wrapping_add(self.pc, imm));
+                    log.branching = Some(self.check_jump_target(u32::wrapping_add(self.pc, imm))?);
                 }
             }
 
@@ -161,14 +257,14 @@ impl DRVSim {
                 // Then load a byte from the memory location at that address, sign-extend
                 // it to 32 bit, and store it in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                let mem_val = self.read_b(adr)?;
-                log_input_values.push(mem_val);
+                let mem_val = self.load(adr, 1)?;
+                log.input_values.push(mem_val);
 
                 let result = ((((mem_val.val & 0xFF) as u8) as i8) as i32) as u32;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::LH { imm, rs1, rd } => {
@@ -177,14 +273,14 @@ impl DRVSim {
                 // Then load a half-word from the memory location at that address, sign-extend
                 // it to 32 bit, and store it in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                let mem_val = self.read_h(adr)?;
-                log_input_values.push(mem_val);
+                let mem_val = self.load(adr, 2)?;
+                log.input_values.push(mem_val);
 
                 let result = ((((mem_val.val & 0xFFFF) as u16) as i16) as i32) as u32;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::LW { imm, rs1, rd } => {
@@ -192,13 +288,13 @@ impl DRVSim {
                 // First add rs1 to the immediate value to obtain the memory address.
                 // Then load a word from the memory location at that address, and store it in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                let mem_val = self.read_w(adr)?;
-                log_input_values.push(mem_val);
+                let mem_val = self.load(adr, 4)?;
+                log.input_values.push(mem_val);
 
-                log_commit_values.push(self.write_register(rd, mem_val.val));
+                log.commit_values.push(self.write_register(rd, mem_val.val));
             }
 
             Instruction::LBU { imm, rs1, rd } => {
@@ -207,14 +303,14 @@ impl DRVSim {
                 // Then load a byte from the memory location at that address, zero-
                 // extend it to 32 bits, and store in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                let mem_val = self.read_b(adr)?;
-                log_input_values.push(mem_val);
+                let mem_val = self.load(adr, 1)?;
+                log.input_values.push(mem_val);
 
                 let result = mem_val.val & 0xFF;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::LHU { imm, rs1, rd } => {
@@ -223,14 +319,14 @@ impl DRVSim {
                 // Then load a half word from the memory location at that address, zero-
                 // extend it to 32 bits, and store in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                let mem_val = self.read_h(adr)?;
-                log_input_values.push(mem_val);
+                let mem_val = self.load(adr, 2)?;
+                log.input_values.push(mem_val);
 
                 let result = mem_val.val & 0xFFFF;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SB { imm, rs2, rs1 } => {
@@ -238,12 +334,12 @@ impl DRVSim {
                 // First add rs1 to the immediate value to obtain the memory address.
                 // Then store the lowest byte from rs2 to memory at that address.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                log_commit_values.push(self.write_b(adr, (inp_rs2.val & 0xFF) as u8)?);
+                log.commit_values.push(self.store(adr, 1, inp_rs2.val)?);
             }
 
             Instruction::SH { imm, rs2, rs1 } => {
@@ -251,12 +347,12 @@ impl DRVSim {
                 // First add rs1 to the immediate value to obtain the memory address.
                 // Then store the lowest half-word from rs2 to memory at that address.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                log_commit_values.push(self.write_h(adr, (inp_rs2.val & 0xFFFF) as u16)?);
+                log.commit_values.push(self.store(adr, 2, inp_rs2.val)?);
             }
 
             Instruction::SW { imm, rs2, rs1 } => {
@@ -264,22 +360,22 @@ impl DRVSim {
                 // First add rs1 to the immediate value to obtain the memory address.
                 // Then store rs2 to memory at that address.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let adr = u32::wrapping_add(inp_rs1.val, imm);
-                log_commit_values.push(self.write_w(adr, inp_rs2.val)?);
+                log.commit_values.push(self.store(adr, 4, inp_rs2.val)?);
             }
 
             Instruction::ADDI { imm, rs1, rd } => {
                 // ADDI rd, rs1, imm:
                 // Add imm to rs1, place result in rd, ignoring overflows.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = u32::wrapping_add(inp_rs1.val, imm);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SLTI { imm, rs1, rd } => {
@@ -287,12 +383,12 @@ impl DRVSim {
                 // Interpret rs1 and imm as signed values. Set rd to 1 if rs1 < imm, otherwise
                 // set rd to 0.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 if (inp_rs1.val as i32) < (imm as i32) {
-                    log_commit_values.push(self.write_register(rd, 1));
+                    log.commit_values.push(self.write_register(rd, 1));
                 } else {
-                    log_commit_values.push(self.write_register(rd, 0));
+                    log.commit_values.push(self.write_register(rd, 0));
                 }
             }
 
@@ -301,12 +397,12 @@ impl DRVSim {
                 // Interpret rs1 and imm as unsigned values, noting that imm still sign extended.
                 // Set rd to 1 if rs1 < imm, otherwise set rd to 0.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 if inp_rs1.val < imm {
-                    log_commit_values.push(self.write_register(rd, 1));
+                    log.commit_values.push(self.write_register(rd, 1));
                 } else {
-                    log_commit_values.push(self.write_register(rd, 0));
+                    log.commit_values.push(self.write_register(rd, 0));
                 }
             }
 
@@ -314,30 +410,30 @@ impl DRVSim {
                 // XORI rd, rs1, imm:
                 // Calculate the bitwise XOR of rs1 and imm, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = inp_rs1.val ^ imm;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::ORI { imm, rs1, rd } => {
                 // ORI rd, rs1, imm:
                 // Calculate the bitwise OR of rs1 and imm, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = inp_rs1.val | imm;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::ANDI { imm, rs1, rd } => {
                 // ANDI rd, rs1, imm:
                 // Calculate the bitwise AND of rs1 and imm, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = inp_rs1.val & imm;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SLLI { shamt, rs1, rd } => {
@@ -345,10 +441,10 @@ impl DRVSim {
                 // Logical-shift rs1 left by the immediate shamt, placing the result
                 // in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = inp_rs1.val << shamt;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SRLI { shamt, rs1, rd } => {
@@ -356,10 +452,10 @@ impl DRVSim {
                 // Logical-shift rs1 right by the immediate shamt, placing the result
                 // in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = inp_rs1.val >> shamt;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SRAI { shamt, rs1, rd } => {
@@ -367,34 +463,34 @@ impl DRVSim {
                 // Arithmetic-shift rs1 right by the immediate shamt, placing the result
                 // in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
 
                 let result = ((inp_rs1.val as i32) >> shamt) as u32;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::ADD { rs2, rs1, rd } => {
                 // ADD rd, rs1, rs2:
                 // Add rs1 to rs2, place result in rd, ignoring overflows.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = u32::wrapping_add(inp_rs1.val, inp_rs2.val);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SUB { rs2, rs1, rd } => {
                 // SUB rd, rs1, rs2:
                 // Subtract rs2 from rs1, place result in rd, ignoring overflows.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = u32::wrapping_sub(inp_rs1.val, inp_rs2.val);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SLL { rs2, rs1, rd } => {
@@ -402,12 +498,12 @@ impl DRVSim {
                 // Logical-shift rs1 left by the amount in the lower 5 bits of rs2, placing
                 // the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = inp_rs1.val << (inp_rs2.val & 0x1F);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SLT { rs2, rs1, rd } => {
@@ -415,14 +511,14 @@ impl DRVSim {
                 // Interpret rs1 and rs2 as signed values. Set rd to 1 if rs1 < rs2, otherwise
                 // set rd to 0.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if (inp_rs1.val as i32) < (inp_rs2.val as i32) {
-                    log_commit_values.push(self.write_register(rd, 1));
+                    log.commit_values.push(self.write_register(rd, 1));
                 } else {
-                    log_commit_values.push(self.write_register(rd, 0));
+                    log.commit_values.push(self.write_register(rd, 0));
                 }
             }
 
@@ -431,14 +527,14 @@ impl DRVSim {
                 // Interpret rs1 and rs2 as unsigned values. Set rd to 1 if rs1 < rs2, otherwise
                 // set rd to 0.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 if inp_rs1.val < inp_rs2.val {
-                    log_commit_values.push(self.write_register(rd, 1));
+                    log.commit_values.push(self.write_register(rd, 1));
                 } else {
-                    log_commit_values.push(self.write_register(rd, 0));
+                    log.commit_values.push(self.write_register(rd, 0));
                 }
             }
 
@@ -446,12 +542,12 @@ impl DRVSim {
                 // XOR rd, rs1, rs2:
                 // Calculate the bitwise XOR of rs1 and rs2, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = inp_rs1.val ^ inp_rs2.val;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SRL { rs2, rs1, rd } => {
@@ -459,12 +555,12 @@ impl DRVSim {
                 // Logical-shift rs1 right by the amount in the lower 5 bits of rs2, placing
                 // the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = inp_rs1.val >> (inp_rs2.val & 0x1F);
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::SRA { rs2, rs1, rd } => {
@@ -472,36 +568,36 @@ impl DRVSim {
                 // Arithmetic-shift rs1 right by the amount in the lower 5 bits of rs2, placing
                 // the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = ((inp_rs1.val as i32) >> (inp_rs2.val & 0x1F)) as u32;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::OR { rs2, rs1, rd } => {
                 // OR rd, rs1, rs2:
                 // Calculate the bitwise OR of rs1 and rs2, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = inp_rs1.val | inp_rs2.val;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::AND { rs2, rs1, rd } => {
                 // AND rd, rs1, rs2:
                 // Calculate the bitwise AND of rs1 and rs2, store the result in rd.
                 let inp_rs1 = self.read_register(rs1)?;
-                log_input_values.push(inp_rs1);
+                log.input_values.push(inp_rs1);
                 let inp_rs2 = self.read_register(rs2)?;
-                log_input_values.push(inp_rs2);
+                log.input_values.push(inp_rs2);
 
                 let result = inp_rs1.val & inp_rs2.val;
-                log_commit_values.push(self.write_register(rd, result));
+                log.commit_values.push(self.write_register(rd, result));
             }
 
             Instruction::FENCE { .. } => {
@@ -509,38 +605,366 @@ impl DRVSim {
                 // All instructions, including their memory access, are atomic.
             }
 
-            Instruction::ECALL => todo!(),
-            Instruction::EBREAK => todo!(),
-            Instruction::DRET => todo!(),
-            Instruction::MRET => todo!(),
+            Instruction::ECALL => {
+                // ECALL:
+                // Request a service from the execution environment by raising an
+                // environment-call exception, entering the trap handler.
+                return Err(Fault::Trap(Trap {
+                    cause: TrapCause::Exception(Exception::EnvironmentCall),
+                    tval: 0,
+                }));
+            }
+
+            Instruction::EBREAK => {
+                // EBREAK:
+                // Return control to the debugging environment by raising a breakpoint
+                // exception, entering the trap handler.
+                return Err(Fault::Trap(Trap {
+                    cause: TrapCause::Exception(Exception::Breakpoint),
+                    tval: self.pc,
+                }));
+            }
+
+            Instruction::DRET => {
+                // DRET:
+                // Leave debug mode, returning to the instruction address saved in Xdpc.
+                if !self.debug_mode {
+                    return Err(self.raise(
+                        anyhow!("Attempted to leave debug mode with DRET while not in debug mode."),
+                        Trap {
+                            cause: TrapCause::Exception(Exception::IllegalInstruction),
+                            tval: inst_word,
+                        },
+                    ));
+                }
+
+                let inp_xdpc = self.read_register(Register::Xdpc)?;
+                log.input_values.push(inp_xdpc);
+
+                self.debug_mode = false;
+                log.branching = Some(inp_xdpc.val);
+            }
+
+            Instruction::MRET => {
+                // MRET:
+                // Return from the trap handler to the instruction address saved in Xmpc.
+                if !self.handling_trap {
+                    return Err(self.raise(
+                        anyhow!("Attempted to return from trap handler with MRET while not handling a trap."),
+                        Trap {
+                            cause: TrapCause::Exception(Exception::IllegalInstruction),
+                            tval: inst_word,
+                        },
+                    ));
+                }
+
+                let inp_xmpc = self.read_register(Register::Xmpc)?;
+                log.input_values.push(inp_xmpc);
+
+                self.handling_trap = false;
+                self.irq_global_enable = self.csr.mpie;
+                self.csr.mpie = true;
+                log.branching = Some(inp_xmpc.val);
+            }
+
+            Instruction::CSRRW { csr, rs1, rd } => {
+                // CSRRW rd, csr, rs1:
+                // Atomically swap the values in the CSR and rs1: Write rs1 to the CSR, and
+                // place its previous value in rd. The CSR is not read if rd is x0.
+                self.check_zicsr(inst_word)?;
+                let inp_rs1 = self.read_register(rs1)?;
+                log.input_values.push(inp_rs1);
+
+                let inp_csr = if rd != Register::X0 {
+                    Some(self.csr_read(csr, inst_word)?)
+                } else {
+                    None
+                };
+                log.commit_values
+                    .push(self.csr_write(csr, inp_rs1.val, inst_word)?);
+
+                if let Some(inp_csr) = inp_csr {
+                    log.input_values.push(inp_csr);
+                    log.commit_values.push(self.write_register(rd, inp_csr.val));
+                }
+            }
+
+            Instruction::CSRRS { csr, rs1, rd } => {
+                // CSRRS rd, csr, rs1:
+                // Place the value of the CSR in rd, and set all bits in the CSR that are set
+                // in rs1. The CSR is not written if rs1 is x0.
+                self.check_zicsr(inst_word)?;
+                let inp_rs1 = self.read_register(rs1)?;
+                log.input_values.push(inp_rs1);
+                let inp_csr = self.csr_read(csr, inst_word)?;
+                log.input_values.push(inp_csr);
+
+                if rs1 != Register::X0 {
+                    let result = inp_csr.val | inp_rs1.val;
+                    log.commit_values
+                        .push(self.csr_write(csr, result, inst_word)?);
+                }
+                log.commit_values.push(self.write_register(rd, inp_csr.val));
+            }
+
+            Instruction::CSRRC { csr, rs1, rd } => {
+                // CSRRC rd, csr, rs1:
+                // Place the value of the CSR in rd, and clear all bits in the CSR that are set
+                // in rs1. The CSR is not written if rs1 is x0.
+                self.check_zicsr(inst_word)?;
+                let inp_rs1 = self.read_register(rs1)?;
+                log.input_values.push(inp_rs1);
+                let inp_csr = self.csr_read(csr, inst_word)?;
+                log.input_values.push(inp_csr);
+
+                if rs1 != Register::X0 {
+                    let result = inp_csr.val & !inp_rs1.val;
+                    log.commit_values
+                        .push(self.csr_write(csr, result, inst_word)?);
+                }
+                log.commit_values.push(self.write_register(rd, inp_csr.val));
+            }
+
+            Instruction::CSRRWI { csr, uimm, rd } => {
+                // CSRRWI rd, csr, uimm:
+                // Write the zero-extended immediate to the CSR, and place its previous value
+                // in rd. The CSR is not read if rd is x0.
+                self.check_zicsr(inst_word)?;
+                let inp_csr = if rd != Register::X0 {
+                    Some(self.csr_read(csr, inst_word)?)
+                } else {
+                    None
+                };
+                log.commit_values
+                    .push(self.csr_write(csr, uimm, inst_word)?);
+
+                if let Some(inp_csr) = inp_csr {
+                    log.input_values.push(inp_csr);
+                    log.commit_values.push(self.write_register(rd, inp_csr.val));
+                }
+            }
+
+            Instruction::CSRRSI { csr, uimm, rd } => {
+                // CSRRSI rd, csr, uimm:
+                // Place the value of the CSR in rd, and set all bits in the CSR that are set
+                // in the zero-extended immediate. The CSR is not written if the immediate is
+                // zero.
+                self.check_zicsr(inst_word)?;
+                let inp_csr = self.csr_read(csr, inst_word)?;
+                log.input_values.push(inp_csr);
+
+                if uimm != 0 {
+                    let result = inp_csr.val | uimm;
+                    log.commit_values
+                        .push(self.csr_write(csr, result, inst_word)?);
+                }
+                log.commit_values.push(self.write_register(rd, inp_csr.val));
+            }
+
+            Instruction::CSRRCI { csr, uimm, rd } => {
+                // CSRRCI rd, csr, uimm:
+                // Place the value of the CSR in rd, and clear all bits in the CSR that are set
+                // in the zero-extended immediate. The CSR is not written if the immediate is
+                // zero.
+                self.check_zicsr(inst_word)?;
+                let inp_csr = self.csr_read(csr, inst_word)?;
+                log.input_values.push(inp_csr);
+
+                if uimm != 0 {
+                    let result = inp_csr.val & !uimm;
+                    log.commit_values
+                        .push(self.csr_write(csr, result, inst_word)?);
+                }
+                log.commit_values.push(self.write_register(rd, inp_csr.val));
+            }
         };
 
-        if let Some(destination) = branching {
-            self.pc = destination;
-        } else {
-            self.pc = u32::wrapping_add(self.pc, 4);
+        Ok(())
+    }
+
+    // Instruction fetches bypass watchpoints. A misaligned PC, such as one restored by MRET/DRET
+    // or set by the host, raises an exception on the fetch.
+    fn fetch(&mut self) -> Result<u32, Fault> {
+        let pc = self.pc;
+        if !pc.is_multiple_of(4) {
+            return Err(self.raise(
+                anyhow!("Attempted to fetch instruction from misaligned address 0x{pc:08x}."),
+                Trap {
+                    cause: TrapCause::Exception(Exception::InstructionAddressMisaligned),
+                    tval: pc,
+                },
+            ));
         }
+        self.bus_read(pc, 4)
+            .map_err(|err| self.access_fault(err, Exception::InstructionAccessFault, pc))
+    }
 
-        Ok(InstLog {
-            pc: log_pc,
-            inst,
-            branching,
-            handling_trap: log_handling_trap,
-            debug_mode: log_debug_mode,
-            input_values: log_input_values,
-            commit_values: log_commit_values,
+    fn decode(&self, inst_word: u32) -> Result<Instruction, Fault> {
+        decode_inst(inst_word).map_err(|err| {
+            self.raise(
+                err,
+                Trap {
+                    cause: TrapCause::Exception(Exception::IllegalInstruction),
+                    tval: inst_word,
+                },
+            )
         })
     }
+
+    // Load 'bytes' bytes from memory at address adr.
+    fn load(&mut self, adr: u32, bytes: u32) -> Result<Value, Fault> {
+        if self.config.fault_mode == FaultMode::Trap && !adr.is_multiple_of(bytes) {
+            return Err(Fault::Trap(Trap {
+                cause: TrapCause::Exception(Exception::LoadAddressMisaligned),
+                tval: adr,
+            }));
+        }
+
+        let result = match bytes {
+            1 => self.read_b(adr),
+            2 => self.read_h(adr),
+            4 => self.read_w(adr),
+            _ => unreachable!(),
+        };
+        result.map_err(|err| self.access_fault(err, Exception::LoadAccessFault, adr))
+    }
+
+    // Store the lowest 'bytes' bytes of val to memory at address adr.
+    fn store(&mut self, adr: u32, bytes: u32, val: u32) -> Result<Value, Fault> {
+        if self.config.fault_mode == FaultMode::Trap && !adr.is_multiple_of(bytes) {
+            return Err(Fault::Trap(Trap {
+                cause: TrapCause::Exception(Exception::StoreAddressMisaligned),
+                tval: adr,
+            }));
+        }
+
+        let result = match bytes {
+            1 => self.write_b(adr, (val & 0xFF) as u8),
+            2 => self.write_h(adr, (val & 0xFFFF) as u16),
+            4 => self.write_w(adr, val),
+            _ => unreachable!(),
+        };
+        result.map_err(|err| self.access_fault(err, Exception::StoreAccessFault, adr))
+    }
+
+    // CSR instructions are illegal unless the Zicsr extension is enabled.
+    fn check_zicsr(&self, inst_word: u32) -> Result<(), Fault> {
+        if self.config.zicsr {
+            Ok(())
+        } else {
+            Err(self.raise(
+                anyhow!("Attempted to execute CSR instruction without Zicsr extension."),
+                Trap {
+                    cause: TrapCause::Exception(Exception::IllegalInstruction),
+                    tval: inst_word,
+                },
+            ))
+        }
+    }
+
+    fn csr_read(&mut self, csr: u32, inst_word: u32) -> Result<Value, Fault> {
+        self.read_csr(csr)
+            .map_err(|err| self.illegal_csr_access(err, inst_word))
+    }
+
+    fn csr_write(&mut self, csr: u32, val: u32, inst_word: u32) -> Result<Value, Fault> {
+        self.write_csr(csr, val)
+            .map_err(|err| self.illegal_csr_access(err, inst_word))
+    }
+
+    // Only accesses to unknown CSRs and writes to read-only CSRs are illegal instructions. All
+    // other CSR errors (such as reading an uninitialized mepc) are simulation errors.
+    fn illegal_csr_access(&self, err: anyhow::Error, inst_word: u32) -> Fault {
+        if err.downcast_ref::<IllegalCsrAccess>().is_some() {
+            self.raise(
+                err,
+                Trap {
+                    cause: TrapCause::Exception(Exception::IllegalInstruction),
+                    tval: inst_word,
+                },
+            )
+        } else {
+            Fault::Error(err)
+        }
+    }
+
+    // Jumps and taken branches to addresses that are not aligned to an instruction
+    // boundary raise an exception on the jump/branch instruction itself.
+    fn check_jump_target(&self, destination: u32) -> Result<u32, Fault> {
+        if self.config.fault_mode == FaultMode::Trap && !destination.is_multiple_of(4) {
+            Err(Fault::Trap(Trap {
+                cause: TrapCause::Exception(Exception::InstructionAddressMisaligned),
+                tval: destination,
+            }))
+        } else {
+            Ok(destination)
+        }
+    }
+
+    // Depending on the fault mode, report a fault either as an architectural exception or
+    // as a simulation error.
+    fn raise(&self, err: anyhow::Error, trap: Trap) -> Fault {
+        match self.config.fault_mode {
+            FaultMode::Error => Fault::Error(err),
+            FaultMode::Trap => Fault::Trap(trap),
+        }
+    }
+
+    // Only accesses not permitted by the memory map raise an access-fault exception. All
+    // other memory errors (such as reading uninitialized memory) are simulation errors.
+    fn access_fault(&self, err: anyhow::Error, cause: Exception, adr: u32) -> Fault {
+        if err.downcast_ref::<AccessFault>().is_some() {
+            self.raise(
+                err,
+                Trap {
+                    cause: TrapCause::Exception(cause),
+                    tval: adr,
+                },
+            )
+        } else {
+            Fault::Error(err)
+        }
+    }
+
+    // Enter the trap handler: Save the address of the current instruction in Xmpc, record the
+    // trap cause and value, and disable interrupts. The caller is responsible for branching to
+    // mtvec.
+    // Traps cannot be nested: Taking a trap while already handling one is a double fault.
+    // Traps are not supported in debug mode.
+    fn enter_trap(&mut self, trap: Trap) -> Result<Value, anyhow::Error> {
+        if self.debug_mode {
+            return Err(anyhow!("Trap at 0x{:08x} while in debug mode.", self.pc));
+        }
+
+        if self.handling_trap {
+            let xmpc = self.read_register(Register::Xmpc)?.val;
+            return Err(anyhow!(
+                "Double fault: Trap at 0x{:08x} while handling trap from 0x{:08x}.",
+                self.pc,
+                xmpc
+            ));
+        }
+
+        self.handling_trap = true;
+        self.csr.mcause = trap.cause.code();
+        self.csr.mtval = trap.tval;
+        self.csr.mpie = self.irq_global_enable;
+        self.irq_global_enable = false;
+        Ok(self.write_register(Register::Xmpc, self.pc))
+    }
 }
 
 // ==== Instruction Unit Tests =====================================================================
 
 #[cfg(test)]
+#[allow(clippy::identity_op, clippy::mixed_case_hex_literals)]
 mod tests {
-    use crate::*;
+    use crate::{inst_log::ValueOrigin, *};
 
     const ROM_START: u32 = 0x1000000;
     const RAM_START: u32 = 0x2000000;
+    const MTVEC: u32 = ROM_START + 0x4000;
+    const DVEC: u32 = ROM_START + 0x6000;
 
     // Create a new simulator with a given set of instructions and register values
     // pre-loaded.
@@ -552,8 +976,8 @@ mod tests {
         // Create new simulator:
         let mut sim = DRVSim::new(DRVSimConfig {
             entry: ROM_START,
-            mtvec: 0,
-            dvec: 0,
+            mtvec: MTVEC,
+            dvec: DVEC,
             mem_regions: vec![
                 MemoryRegionConfig {
                     adr_range: ROM_START..ROM_START + 0x8000,
@@ -567,6 +991,8 @@ mod tests {
                 },
             ],
             reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
         });
 
         // Load instructions, memory and registers:
@@ -1404,4 +1830,558 @@ mod tests {
         RAM_START,
         0xDEADBEEF_u32
     );
+
+    // ==== Trap Instructions ====
+
+    // Assert that instruction $inst enters the trap handler, saving its address in Xmpc.
+    macro_rules! test_trap_inst {
+        ($name:tt, $inst:expr) => {
+            #[test]
+            fn $name() {
+                let mut sim = new_simulator(vec![0x00000013, $inst], vec![], vec![]);
+                // Step:
+                println!("{:?}", sim.step().unwrap());
+                let log = sim.step().unwrap();
+                println!("{:?}", log);
+                assert!(!log.handling_trap);
+                assert_eq!(log.branching, Some(MTVEC));
+                // Compare PC:
+                let is = sim.pc;
+                println!("PC is: 0x{:x}, expect: 0x{:x}", is, MTVEC);
+                assert_eq!(is, MTVEC);
+                // Compare Xmpc:
+                let is = sim.read_register(Register::Xmpc).unwrap().val;
+                println!("Xmpc is: 0x{:x}, expect: 0x{:x}", is, ROM_START + 4);
+                assert_eq!(is, ROM_START + 4);
+                assert!(sim.handling_trap);
+            }
+        };
+    }
+
+    // ECALL
+    test_trap_inst!(inst_ecall, 0x00000073_u32);
+    // EBREAK
+    test_trap_inst!(inst_ebreak, 0x00100073_u32);
+
+    #[test]
+    fn inst_mret() {
+        // ECALL, ADDI x0, x0, 0
+        let mut sim = new_simulator(vec![0x00000073, 0x00000013], vec![], vec![]);
+        // MRET at mtvec:
+        sim.program_w(MTVEC, 0x30200073).unwrap();
+
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(!log.handling_trap);
+
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(log.handling_trap);
+        assert_eq!(log.branching, Some(ROM_START));
+        assert_eq!(sim.pc, ROM_START);
+        assert!(!sim.handling_trap());
+    }
+
+    #[test]
+    fn inst_mret_outside_trap() {
+        // MRET
+        let mut sim = new_simulator(vec![0x30200073], vec![(Register::Xmpc, ROM_START)], vec![]);
+        assert!(sim.step().is_err());
+    }
+
+    #[test]
+    fn double_fault() {
+        // ECALL
+        let mut sim = new_simulator(vec![0x00000073], vec![], vec![]);
+        // ECALL at mtvec:
+        sim.program_w(MTVEC, 0x00000073).unwrap();
+
+        println!("{:?}", sim.step().unwrap());
+        let err = sim.step().unwrap_err();
+        println!("{:?}", err);
+        assert!(err.to_string().contains("Double fault"));
+    }
+
+    #[test]
+    fn debug_mode() {
+        // ADDI x1, x1, 1, ADDI x1, x1, 1
+        let mut sim = new_simulator(
+            vec![0x00108093, 0x00108093],
+            vec![(Register::X1, 0)],
+            vec![],
+        );
+        // ADDI x2, x0, 1, DRET at dvec:
+        sim.program_w(DVEC, 0x00100113).unwrap();
+        sim.program_w(DVEC + 4, 0x7b200073).unwrap();
+
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(!log.debug_mode);
+
+        // Enter debug mode:
+        sim.set_dbg_req(true);
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(log.debug_mode);
+        assert_eq!(log.pc, DVEC);
+        assert_eq!(
+            sim.read_register(Register::Xdpc).unwrap().val,
+            ROM_START + 4
+        );
+        sim.set_dbg_req(false);
+
+        // DRET:
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(log.debug_mode);
+        assert_eq!(log.branching, Some(ROM_START + 4));
+        assert!(!sim.debug_mode());
+
+        let log = sim.step().unwrap();
+        println!("{:?}", log);
+        assert!(!log.debug_mode);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+        assert_eq!(sim.read_register(Register::X2).unwrap().val, 1);
+    }
+
+    #[test]
+    fn inst_dret_outside_debug_mode() {
+        // DRET
+        let mut sim = new_simulator(vec![0x7b200073], vec![(Register::Xdpc, ROM_START)], vec![]);
+        assert!(sim.step().is_err());
+    }
+
+    // ==== Exceptions ====
+
+    // Assert that instruction $inst, given $rs1 in x1 and $rs2 in x2, raises exception
+    // $cause with trap value $tval if the simulator traps on faults, and stops the simulation
+    // otherwise.
+    macro_rules! test_exception {
+        ($name:tt, $inst:expr, $rs1:expr, $rs2:expr, $cause:expr, $tval:expr) => {
+            #[test]
+            fn $name() {
+                let mut sim = new_simulator(
+                    vec![$inst],
+                    vec![
+                        (Register::X1, $rs1),
+                        (Register::X2, $rs2),
+                        (Register::X3, 0),
+                    ],
+                    vec![],
+                );
+                sim.config.fault_mode = FaultMode::Trap;
+                // Step:
+                let log = sim.step().unwrap();
+                println!("{}", log.to_log_string());
+                // Compare trap:
+                assert_eq!(
+                    log.trap,
+                    Some(trap::Trap {
+                        cause: trap::TrapCause::Exception($cause),
+                        tval: $tval
+                    })
+                );
+                assert_eq!(sim.pc, MTVEC);
+                assert_eq!(sim.read_register(Register::Xmpc).unwrap().val, ROM_START);
+                // The faulting instruction must not modify x3:
+                assert_eq!(sim.read_register(Register::X3).unwrap().val, 0);
+
+                let mut sim = new_simulator(
+                    vec![$inst],
+                    vec![
+                        (Register::X1, $rs1),
+                        (Register::X2, $rs2),
+                        (Register::X3, 0),
+                    ],
+                    vec![],
+                );
+                assert!(sim.step().is_err());
+            }
+        };
+    }
+
+    // Unknown opcode:
+    test_exception!(
+        exception_illegal_inst,
+        0xFFFFFFFF_u32,
+        0,
+        0,
+        trap::Exception::IllegalInstruction,
+        0xFFFFFFFF
+    );
+    // LW x3, 0x0(x1)
+    test_exception!(
+        exception_load_unmapped,
+        0x0000a183_u32,
+        0x100,
+        0,
+        trap::Exception::LoadAccessFault,
+        0x100
+    );
+    // SW x2, 0x0(x1)
+    test_exception!(
+        exception_store_rom,
+        0x0020a023_u32,
+        ROM_START + 0x100,
+        0,
+        trap::Exception::StoreAccessFault,
+        ROM_START + 0x100
+    );
+    // MRET
+    test_exception!(
+        exception_mret_outside_trap,
+        0x30200073_u32,
+        0,
+        0,
+        trap::Exception::IllegalInstruction,
+        0x30200073
+    );
+
+    // Assert that instruction $inst, given $rs1 in x1 and $rs2 in x2, raises exception
+    // $cause with trap value $tval if the simulator traps on faults, and executes normally
+    // otherwise.
+    macro_rules! test_misaligned_exception {
+        ($name:tt, $inst:expr, $rs1:expr, $rs2:expr, $cause:expr, $tval:expr) => {
+            #[test]
+            fn $name() {
+                let mut sim = new_simulator(
+                    vec![$inst],
+                    vec![
+                        (Register::X1, $rs1),
+                        (Register::X2, $rs2),
+                        (Register::X3, 0),
+                    ],
+                    vec![],
+                );
+                sim.config.fault_mode = FaultMode::Trap;
+                // Step:
+                let log = sim.step().unwrap();
+                println!("{}", log.to_log_string());
+                // Compare trap:
+                assert_eq!(
+                    log.trap,
+                    Some(trap::Trap {
+                        cause: trap::TrapCause::Exception($cause),
+                        tval: $tval
+                    })
+                );
+                assert_eq!(sim.pc, MTVEC);
+                assert_eq!(sim.read_register(Register::X3).unwrap().val, 0);
+
+                let mut sim = new_simulator(
+                    vec![$inst],
+                    vec![
+                        (Register::X1, $rs1),
+                        (Register::X2, $rs2),
+                        (Register::X3, 0),
+                    ],
+                    vec![],
+                );
+                let log = sim.step().unwrap();
+                println!("{}", log.to_log_string());
+                assert_eq!(log.trap, None);
+            }
+        };
+    }
+
+    // LW x3, 0x0(x1)
+    test_misaligned_exception!(
+        exception_load_misaligned,
+        0x0000a183_u32,
+        RAM_START + 2,
+        0,
+        trap::Exception::LoadAddressMisaligned,
+        RAM_START + 2
+    );
+    // SH x2, 0x0(x1)
+    test_misaligned_exception!(
+        exception_store_misaligned,
+        0x00209023_u32,
+        RAM_START + 1,
+        0,
+        trap::Exception::StoreAddressMisaligned,
+        RAM_START + 1
+    );
+    // JALR x3, 0x2(x1)
+    test_misaligned_exception!(
+        exception_jump_misaligned,
+        0x002081e7_u32,
+        ROM_START,
+        0,
+        trap::Exception::InstructionAddressMisaligned,
+        ROM_START + 2
+    );
+
+    #[test]
+    fn exception_fetch_misaligned() {
+        // MRET, to a misaligned Xmpc:
+        let mut sim = new_simulator(
+            vec![0x30200073, 0x00000013, 0x00000013],
+            vec![(Register::Xmpc, ROM_START + 6)],
+            vec![],
+        );
+        sim.config.fault_mode = FaultMode::Trap;
+        sim.handling_trap = true;
+        sim.step().unwrap();
+        assert_eq!(sim.pc, ROM_START + 6);
+        let log = sim.step().unwrap();
+        assert_eq!(
+            log.trap,
+            Some(trap::Trap {
+                cause: trap::TrapCause::Exception(trap::Exception::InstructionAddressMisaligned),
+                tval: ROM_START + 6
+            })
+        );
+        assert_eq!(sim.pc, MTVEC);
+
+        let mut sim = new_simulator(vec![0x00000013, 0x00000013], vec![], vec![]);
+        sim.set_pc(ROM_START + 2);
+        assert!(sim.step().is_err());
+    }
+
+    #[test]
+    fn exception_uninit_read_is_error() {
+        // LW x3, 0x0(x1)
+        let mut sim = new_simulator(
+            vec![0x0000a183],
+            vec![(Register::X1, ROM_START + 0x100)],
+            vec![],
+        );
+        sim.config.fault_mode = FaultMode::Trap;
+        assert!(sim.step().is_err());
+    }
+
+    // ==== Interrupts ====
+
+    #[test]
+    fn interrupt() {
+        // ADDI x1, x1, 1, ADDI x1, x1, 1
+        let mut sim = new_simulator(
+            vec![0x00108093, 0x00108093],
+            vec![(Register::X1, 0)],
+            vec![],
+        );
+        // MRET at mtvec:
+        sim.program_w(MTVEC, 0x30200073).unwrap();
+
+        // Pending but not enabled:
+        sim.raise_irq(3);
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(log.trap, None);
+
+        // Enabled:
+        sim.set_irq_enable(1 << 3);
+        sim.set_global_irq_enable(true);
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(log.pc, ROM_START + 4);
+        assert_eq!(log.inst, None);
+        assert_eq!(
+            log.trap,
+            Some(trap::Trap {
+                cause: trap::TrapCause::Interrupt(3),
+                tval: 0
+            })
+        );
+        assert!(log.trap.unwrap().cause.is_interrupt());
+        assert_eq!(sim.pc, MTVEC);
+        assert_eq!(
+            sim.read_register(Register::Xmpc).unwrap().val,
+            ROM_START + 4
+        );
+
+        // Interrupts are not taken while handling a trap:
+        sim.lower_irq(3);
+        sim.raise_irq(4);
+        sim.set_irq_enable(0xFFFFFFFF);
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(log.trap, None);
+        assert_eq!(sim.pc, ROM_START + 4);
+
+        // Interrupted instruction is executed after return:
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(
+            log.trap.map(|trap| trap.cause),
+            Some(trap::TrapCause::Interrupt(4))
+        );
+        sim.lower_irq(4);
+        sim.step().unwrap();
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(log.trap, None);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+    }
+
+    // ==== Zicsr ====
+
+    #[test]
+    fn zicsr_trap_handler() {
+        let mut sim = new_simulator(
+            vec![
+                0x30509073, // CSRRW x0, mtvec, x1
+                0x00000073, // ECALL
+            ],
+            vec![(Register::X1, RAM_START)],
+            vec![],
+        );
+        sim.config.zicsr = true;
+        // CSRRS x3, mcause, x0 and CSRRS x4, mepc, x0 at the new mtvec:
+        sim.program_w(RAM_START, 0x342021f3).unwrap();
+        sim.program_w(RAM_START + 4, 0x34102273).unwrap();
+
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert!(matches!(
+            log.commit_values[0].origin,
+            ValueOrigin::Csr(csr::MTVEC)
+        ));
+        assert_eq!(sim.read_csr(csr::MTVEC).unwrap().val, RAM_START);
+
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(sim.pc, RAM_START);
+
+        println!("{}", sim.step().unwrap().to_log_string());
+        println!("{}", sim.step().unwrap().to_log_string());
+        assert_eq!(sim.read_register(Register::X3).unwrap().val, 11);
+        assert_eq!(sim.read_register(Register::X4).unwrap().val, ROM_START + 4);
+        assert_eq!(sim.read_csr(csr::MTVAL).unwrap().val, 0);
+
+        // ECALL does not retire:
+        assert_eq!(sim.cycle(), 4);
+        assert_eq!(sim.instret(), 3);
+    }
+
+    #[test]
+    fn zicsr_counters() {
+        let mut sim = new_simulator(
+            vec![
+                0x00000013, // ADDI x0, x0, 0
+                0x00000013, // ADDI x0, x0, 0
+                0xc00021f3, // CSRRS x3, cycle, x0
+                0xc0201273, // CSRRW x4, instret, x0
+            ],
+            vec![],
+            vec![],
+        );
+        sim.config.zicsr = true;
+        sim.config.fault_mode = FaultMode::Trap;
+
+        for _ in 0..4 {
+            println!("{}", sim.step().unwrap().to_log_string());
+        }
+        assert_eq!(sim.read_register(Register::X3).unwrap().val, 2);
+        // Counter CSRs are read-only:
+        assert_eq!(sim.pc, MTVEC);
+        assert_eq!(
+            sim.read_csr(csr::MCAUSE).unwrap().val,
+            trap::Exception::IllegalInstruction.code()
+        );
+        assert_eq!(sim.read_csr(csr::MTVAL).unwrap().val, 0xc0201273);
+    }
+
+    #[test]
+    fn zicsr_write_side_effects() {
+        let mut sim = new_simulator(
+            vec![
+                0x30509073, // CSRRW x0, mtvec, x1
+                0xb0011073, // CSRRW x0, mcycle, x2
+                0x341021f3, // CSRRS x3, mepc, x0
+            ],
+            vec![(Register::X1, RAM_START | 0b11), (Register::X2, 100)],
+            vec![],
+        );
+        sim.config.zicsr = true;
+        sim.config.fault_mode = FaultMode::Trap;
+
+        // The stored value is logged:
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(log.commit_values[0].val, RAM_START);
+
+        // Writing a counter overrides its increment:
+        println!("{}", sim.step().unwrap().to_log_string());
+        assert_eq!(sim.cycle(), 100);
+        assert_eq!(sim.instret(), 2);
+
+        // Reading an uninitialized mepc is a simulation error, not an illegal instruction:
+        assert!(sim.step().is_err());
+        assert_eq!(sim.pc, ROM_START + 8);
+    }
+
+    #[test]
+    fn zicsr_disabled() {
+        // CSRRS x3, mcause, x0
+        let mut sim = new_simulator(vec![0x342021f3], vec![], vec![]);
+        assert!(sim.step().is_err());
+    }
+
+    #[test]
+    fn zicsr_interrupt_enable() {
+        let mut sim = new_simulator(
+            vec![
+                0x30446073, // CSRRSI x0, mie, 0x8
+                0x30046073, // CSRRSI x0, mstatus, 0x8
+                0x00000013, // ADDI x0, x0, 0
+            ],
+            vec![],
+            vec![],
+        );
+        sim.config.zicsr = true;
+        sim.raise_irq(3);
+
+        println!("{}", sim.step().unwrap().to_log_string());
+        assert_eq!(sim.irq_enable(), 1 << 3);
+        println!("{}", sim.step().unwrap().to_log_string());
+        assert!(sim.global_irq_enable());
+        let log = sim.step().unwrap();
+        println!("{}", log.to_log_string());
+        assert_eq!(
+            log.trap.map(|trap| trap.cause),
+            Some(trap::TrapCause::Interrupt(3))
+        );
+        assert_eq!(sim.read_csr(csr::MCAUSE).unwrap().val, 0x80000003);
+        // Interrupts are disabled while handling the trap:
+        assert_eq!(sim.read_csr(csr::MSTATUS).unwrap().val & 0x88, 0x80);
+    }
+
+    // ==== Predecode Cache ====
+
+    #[test]
+    fn self_modifying_code() {
+        let mut sim = new_simulator(
+            vec![
+                0x02000137, // LUI x2, 0x2000
+                0x000101e7, // JALR x3, 0x0(x2)
+                0x00412023, // SW x4, 0x0(x2)
+                0x000101e7, // JALR x3, 0x0(x2)
+            ],
+            vec![(Register::X4, 0x00200093)], // ADDI x1, x0, 2
+            vec![
+                (RAM_START, 0x00100093),     // ADDI x1, x0, 1
+                (RAM_START + 4, 0x00018067), // JALR x0, 0x0(x3)
+            ],
+        );
+
+        for _ in 0..4 {
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 1);
+
+        // Stored instruction replaces the cached one:
+        for _ in 0..3 {
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.pc, RAM_START + 4);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+
+        // So do programmed instructions:
+        sim.program_w(RAM_START, 0x00300093).unwrap(); // ADDI x1, x0, 3
+        sim.pc = RAM_START;
+        sim.step().unwrap();
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 3);
+    }
 }
@@ -1,19 +1,46 @@
+pub mod asm;
+pub mod breakpoint;
+pub mod csr;
+pub mod device;
+mod export;
+pub mod gdb;
 mod inst;
 mod inst_decoding;
+mod inst_encoding;
 pub mod inst_log;
 mod inst_sim;
+mod loader;
 mod memory;
+pub mod registers;
+pub mod run;
+pub mod snapshot;
+pub mod symbols;
+pub mod timer;
+pub mod trap;
+pub mod uart;
+mod undo;
 
-use crate::{inst::Register, inst_log::Value, memory::Memory};
+// Instructions can be encoded and decoded, to generate or patch programs:
+pub use crate::{
+    inst::{Instruction, Register},
+    inst_decoding::decode_inst,
+};
+
+use crate::{
+    breakpoint::{Breakpoints, MemAccess},
+    csr::CsrFile,
+    device::Device,
+    inst_log::Value,
+    memory::Memory,
+    registers::RegisterFile,
+    symbols::SymbolTable,
+    undo::UndoJournal,
+};
 use anyhow::anyhow;
 use rand::Rng;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::cell::RefCell;
 use std::ops::Range;
-use std::path::PathBuf;
-
-use elf::endian::LittleEndian;
-use elf::ElfBytes;
+use std::rc::Rc;
 
 // ===== Type Definitions ==========================================================================
 
@@ -27,10 +54,17 @@ pub enum ValueInit {
     FixedWord(u32),
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum MemoryRegionType {
     RAM,
     ROM,
+    Device(Rc<RefCell<dyn Device>>), // Memory-mapped peripheral. The region's init is ignored.
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FaultMode {
+    Error, // Stop the simulation with an error.
+    Trap,  // Raise an architectural exception, handled by the trap handler.
 }
 
 #[derive(Clone)]
@@ -40,26 +74,80 @@ pub struct MemoryRegionConfig {
     pub region_type: MemoryRegionType,
 }
 
+#[derive(Clone)]
 pub struct DRVSimConfig {
     pub entry: u32, // Address of first instruction to be executed.
-    pub mtvec: u32, // Address of interrupt handler.
+    pub mtvec: u32, // Address of interrupt handler after reset.
     pub dvec: u32,  // Address of debug program buffer.
     pub mem_regions: Vec<MemoryRegionConfig>, // Available memory.
     pub reg_init: ValueInit, // Initial value of registers after reset.
+    pub fault_mode: FaultMode, // Handling of illegal instructions and faulting memory accesses.
+    pub zicsr: bool, // Enable the Zicsr extension (CSR instructions).
+}
+
+// Memory access that is not permitted by the memory map:
+#[derive(Debug, Clone, Copy)]
+pub enum AccessFault {
+    Unmapped { adr: u32 },
+    CrossesRegionBoundary { adr: u32, bytes: u32 },
+    WriteProtected { adr: u32 },
 }
 
+// Memory regions are looked up through a table of 64KiB pages:
+const REGION_PAGE_BITS: u32 = 16;
+const NO_REGION: u16 = u16::MAX;
+
+#[derive(Clone)]
 struct MemoryRegion {
     adr_range: Range<u32>,
-    mem: Memory,
+    backing: RegionBacking,
+}
+
+#[derive(Clone)]
+enum RegionBacking {
+    Memory(Memory),
+    Device(Rc<RefCell<dyn Device>>),
 }
 
 pub struct DRVSim {
-    core_reg: HashMap<Register, u32>, // Core registers.
-    pc: u32,                          // Program Counter.
-    mems: Vec<MemoryRegion>,          // Memories.
-    config: DRVSimConfig,             // Simulation Settings
+    core_reg: RegisterFile,   // Core registers.
+    pc: u32,                  // Program Counter.
+    handling_trap: bool,      // Core is executing the trap handler.
+    debug_mode: bool,         // Core is executing the debug program buffer.
+    dbg_req: bool,            // Debug request input.
+    irq_pending: u32,         // Level of each interrupt line.
+    device_irq: u32,          // Interrupt lines asserted by devices.
+    irq_enable: u32,          // Interrupt line enable mask.
+    irq_global_enable: bool,  // Global interrupt enable.
+    csr: CsrFile,             // Control and status registers.
+    breakpoints: Breakpoints, // Breakpoints and watchpoints.
+    undo: UndoJournal,        // State overwritten by recent steps.
+    symbols: SymbolTable,     // Symbols loaded from ELF files.
+    mems: Vec<MemoryRegion>,  // Memories.
+    region_pages: Vec<u16>,   // Index of a region overlapping each region page.
+    config: DRVSimConfig,     // Simulation Settings
 }
 
+// ===== AccessFault Implementation ================================================================
+
+impl std::fmt::Display for AccessFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessFault::Unmapped { adr } => {
+                write!(f, "Access to unknown memory address 0x{adr:x}")
+            }
+            AccessFault::CrossesRegionBoundary { adr, bytes } => {
+                write!(f, "Attempted to access {bytes} bytes of memory at 0x{adr:0x}, which crosses a memory region boundary.")
+            }
+            AccessFault::WriteProtected { adr } => {
+                write!(f, "Attempted to write to read-only memory at 0x{adr:x}.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccessFault {}
+
 // ===== DRVSim Implementation =====================================================================
 
 impl DRVSim {
@@ -67,105 +155,298 @@ impl DRVSim {
         let mut mem = vec![];
 
         for config in config.mem_regions.iter() {
-            let start_adr = config.adr_range.start;
+            let adr_range = config.adr_range.clone();
             let init = config.init;
 
-            let memory = match config.region_type {
-                MemoryRegionType::RAM => Memory::new(start_adr, init, false),
-                MemoryRegionType::ROM => Memory::new(start_adr, init, true),
+            let backing = match &config.region_type {
+                MemoryRegionType::RAM => RegionBacking::Memory(Memory::new(adr_range, init, false)),
+                MemoryRegionType::ROM => RegionBacking::Memory(Memory::new(adr_range, init, true)),
+                MemoryRegionType::Device(device) => RegionBacking::Device(device.clone()),
             };
 
             mem.push(MemoryRegion {
                 adr_range: config.adr_range.clone(),
-                mem: memory,
+                backing,
             });
         }
 
         DRVSim {
-            core_reg: HashMap::new(),
+            core_reg: RegisterFile::default(),
             pc: config.entry,
+            handling_trap: false,
+            debug_mode: false,
+            dbg_req: false,
+            irq_pending: 0,
+            device_irq: 0,
+            irq_enable: 0,
+            irq_global_enable: false,
+            csr: CsrFile::new(config.mtvec),
+            breakpoints: Breakpoints::default(),
+            undo: UndoJournal::default(),
+            symbols: SymbolTable::default(),
+            region_pages: region_pages(&mem),
             mems: mem,
             config,
         }
     }
 
-    fn find_mem_region(&mut self, adr: u32, access_len: u32) -> Result<usize, anyhow::Error> {
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    // Set the address of the next instruction to be executed.
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+        self.breakpoints.resume_pc = None;
+    }
+
+    pub fn handling_trap(&self) -> bool {
+        self.handling_trap
+    }
+
+    pub fn debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+
+    // Set the level of the debug request input. While asserted, the core enters debug
+    // mode before executing the next instruction, unless it is already in debug mode.
+    pub fn set_dbg_req(&mut self, dbg_req: bool) {
+        self.dbg_req = dbg_req;
+    }
+
+    fn find_mem_region(&self, adr: u32, access_len: u32) -> Result<usize, anyhow::Error> {
         assert!(access_len > 0 && access_len <= 4);
-        for (idx, region) in self.mems.iter().enumerate() {
-            if region.adr_range.contains(&adr) {
-                if region.adr_range.contains(&(adr + access_len - 1)) {
-                    return Ok(idx);
-                } else {
-                    return Err(anyhow!("Attempted to access {access_len} bytes of memory at 0x{adr:0x}, which crosses a memory region boundary."));
-                }
+
+        // Pages shared by several regions, and unmapped addresses, fall back to a full search:
+        let page_region = self.region_pages[(adr >> REGION_PAGE_BITS) as usize];
+        let idx = if page_region != NO_REGION
+            && self.mems[page_region as usize].adr_range.contains(&adr)
+        {
+            page_region as usize
+        } else {
+            self.mems
+                .iter()
+                .position(|region| region.adr_range.contains(&adr))
+                .ok_or(AccessFault::Unmapped { adr })?
+        };
+
+        if self.mems[idx]
+            .adr_range
+            .contains(&(adr.wrapping_add(access_len - 1)))
+        {
+            Ok(idx)
+        } else {
+            Err(AccessFault::CrossesRegionBoundary {
+                adr,
+                bytes: access_len,
+            }
+            .into())
+        }
+    }
+
+    // Read from the memory or device mapped at adr:
+    fn bus_read(&mut self, adr: u32, bytes: u32) -> Result<u32, anyhow::Error> {
+        let region_idx = self.find_mem_region(adr, bytes)?;
+        self.journal_mem_init(region_idx, adr, bytes);
+        let region = &mut self.mems[region_idx];
+        match &mut region.backing {
+            RegionBacking::Memory(mem) => match bytes {
+                1 => Ok(mem.read_b(adr)? as u32),
+                2 => Ok(mem.read_h(adr)? as u32),
+                _ => mem.read_w(adr),
+            },
+            RegionBacking::Device(device) => device
+                .borrow_mut()
+                .read(adr - region.adr_range.start, bytes),
+        }
+    }
+
+    // Write to the memory or device mapped at adr, returning the previous value:
+    fn bus_write(&mut self, adr: u32, bytes: u32, val: u32) -> Result<Option<u32>, anyhow::Error> {
+        let region_idx = self.find_mem_region(adr, bytes)?;
+        let prev = self.mem_prev_bytes(region_idx, adr, bytes);
+        let region = &mut self.mems[region_idx];
+        match &mut region.backing {
+            RegionBacking::Memory(mem) => match bytes {
+                1 => mem.write_b(adr, val as u8)?,
+                2 => mem.write_h(adr, val as u16)?,
+                _ => mem.write_w(adr, val)?,
+            },
+            RegionBacking::Device(device) => {
+                device
+                    .borrow_mut()
+                    .write(adr - region.adr_range.start, bytes, val)?;
             }
         }
 
-        Err(anyhow!("Access to unknown memory address 0x{adr:x}"))
+        // Only journal writes that succeeded:
+        Ok(prev.and_then(|prev| self.journal_mem_write(adr, bytes, val, prev)))
     }
 
+    // Predecoded instruction and instruction word at adr. Instructions in devices are never
+    // cached.
+    fn cached_inst(&self, adr: u32) -> Option<(Instruction, u32)> {
+        if !adr.is_multiple_of(4) {
+            return None;
+        }
+        let region_idx = self.find_mem_region(adr, 4).ok()?;
+        match &self.mems[region_idx].backing {
+            RegionBacking::Memory(mem) => mem.cached_inst(adr),
+            RegionBacking::Device(_) => None,
+        }
+    }
+
+    fn cache_inst(&mut self, adr: u32, inst: (Instruction, u32)) {
+        if !adr.is_multiple_of(4) {
+            return;
+        }
+        if let Ok(region_idx) = self.find_mem_region(adr, 4) {
+            if let RegionBacking::Memory(mem) = &mut self.mems[region_idx].backing {
+                mem.cache_inst(adr, inst);
+            }
+        }
+    }
+
+    // Tick all devices once, and sample their interrupt lines:
+    fn tick_devices(&mut self) {
+        self.device_irq = 0;
+        for region in self.mems.iter() {
+            if let RegionBacking::Device(device) = &region.backing {
+                let mut device = device.borrow_mut();
+                device.tick();
+                self.device_irq |= device.irq_lines();
+            }
+        }
+    }
+
+    // Initialize memory, ignoring write protection. Devices are written to directly.
     pub fn program_b(&mut self, adr: u32, val: u8) -> Result<(), anyhow::Error> {
         let region_idx = self.find_mem_region(adr, 1)?;
-        self.mems[region_idx].mem.program_b(adr, val);
+        let region = &mut self.mems[region_idx];
+        match &mut region.backing {
+            RegionBacking::Memory(mem) => mem.program_b(adr, val),
+            RegionBacking::Device(device) => {
+                device
+                    .borrow_mut()
+                    .write(adr - region.adr_range.start, 1, val as u32)?;
+            }
+        }
         Ok(())
     }
 
     pub fn program_w(&mut self, adr: u32, val: u32) -> Result<(), anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 4)?;
-        self.mems[region_idx].mem.program_b(adr, (val & 0xFF) as u8);
-        self.mems[region_idx]
-            .mem
-            .program_b(adr + 1, ((val >> 8) & 0xFF) as u8);
-        self.mems[region_idx]
-            .mem
-            .program_b(adr + 2, ((val >> 16) & 0xFF) as u8);
-        self.mems[region_idx]
-            .mem
-            .program_b(adr + 3, ((val >> 24) & 0xFF) as u8);
+        self.find_mem_region(adr, 4)?;
+        self.program_b(adr, (val & 0xFF) as u8)?;
+        self.program_b(adr + 1, ((val >> 8) & 0xFF) as u8)?;
+        self.program_b(adr + 2, ((val >> 16) & 0xFF) as u8)?;
+        self.program_b(adr + 3, ((val >> 24) & 0xFF) as u8)?;
         Ok(())
     }
 
     pub fn write_b(&mut self, adr: u32, val: u8) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 1)?;
-        self.mems[region_idx].mem.write_b(adr, val)?;
-        Ok(Value::memory_value(adr, 1, val as u32))
+        let prev = self.bus_write(adr, 1, val as u32)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 1,
+            val: val as u32,
+            write: true,
+        });
+        Ok(Value::memory_value(adr, 1, val as u32).with_prev(prev))
     }
 
     pub fn write_h(&mut self, adr: u32, val: u16) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 2)?;
-        self.mems[region_idx].mem.write_h(adr, val)?;
-        Ok(Value::memory_value(adr, 2, val as u32))
+        let prev = self.bus_write(adr, 2, val as u32)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 2,
+            val: val as u32,
+            write: true,
+        });
+        Ok(Value::memory_value(adr, 2, val as u32).with_prev(prev))
     }
 
     pub fn write_w(&mut self, adr: u32, val: u32) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 4)?;
-        self.mems[region_idx].mem.write_w(adr, val)?;
-        Ok(Value::memory_value(adr, 4, val))
+        let prev = self.bus_write(adr, 4, val)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 4,
+            val,
+            write: true,
+        });
+        Ok(Value::memory_value(adr, 4, val).with_prev(prev))
     }
 
     pub fn read_b(&mut self, adr: u32) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 1)?;
-        let val = self.mems[region_idx].mem.read_b(adr)?;
-        Ok(Value::memory_value(adr, 1, val as u32))
+        let val = self.bus_read(adr, 1)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 1,
+            val,
+            write: false,
+        });
+        Ok(Value::memory_value(adr, 1, val))
     }
 
     pub fn read_h(&mut self, adr: u32) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 2)?;
-        let val = self.mems[region_idx].mem.read_h(adr)?;
-        Ok(Value::memory_value(adr, 2, val as u32))
+        let val = self.bus_read(adr, 2)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 2,
+            val,
+            write: false,
+        });
+        Ok(Value::memory_value(adr, 2, val))
     }
 
     pub fn read_w(&mut self, adr: u32) -> Result<Value, anyhow::Error> {
-        let region_idx = self.find_mem_region(adr, 4)?;
-        let val = self.mems[region_idx].mem.read_w(adr)?;
+        let val = self.bus_read(adr, 4)?;
+        self.check_watchpoints(MemAccess {
+            adr,
+            bytes: 4,
+            val,
+            write: false,
+        });
         Ok(Value::memory_value(adr, 4, val))
     }
 
+    // Contents of memory, without initializing it or triggering watchpoints. None if any byte is
+    // uninitialized. Devices cannot be peeked, since reading them may have side effects.
+    pub fn peek_b(&self, adr: u32) -> Result<Option<u8>, anyhow::Error> {
+        Ok(self.peek_mem(adr, 1)?.peek_b(adr))
+    }
+
+    pub fn peek_h(&self, adr: u32) -> Result<Option<u16>, anyhow::Error> {
+        Ok(self.peek_mem(adr, 2)?.peek_h(adr))
+    }
+
+    pub fn peek_w(&self, adr: u32) -> Result<Option<u32>, anyhow::Error> {
+        Ok(self.peek_mem(adr, 4)?.peek_w(adr))
+    }
+
+    fn peek_mem(&self, adr: u32, bytes: u32) -> Result<&Memory, anyhow::Error> {
+        let region_idx = self.find_mem_region(adr, bytes)?;
+        match &self.mems[region_idx].backing {
+            RegionBacking::Memory(mem) => Ok(mem),
+            RegionBacking::Device(_) => {
+                Err(anyhow!("Attempted to peek device memory at 0x{adr:x}."))
+            }
+        }
+    }
+
+    // Contents of a register, without initializing it. None if uninitialized.
+    pub fn peek_register(&self, reg: Register) -> Option<u32> {
+        if reg == Register::X0 {
+            Some(0)
+        } else {
+            self.core_reg.get(reg)
+        }
+    }
+
     pub fn read_register(&mut self, reg: Register) -> Result<Value, anyhow::Error> {
         if reg == Register::X0 {
             Ok(Value::register_value(reg, 0))
         } else {
-            if let Entry::Vacant { .. } = self.core_reg.entry(reg) {
+            if self.core_reg.get(reg).is_none() {
                 let val = match self.config.reg_init {
                     ValueInit::Random => rand::thread_rng().gen(),
                     ValueInit::Zero => 0,
@@ -176,34 +457,36 @@ impl DRVSim {
                         return Err(anyhow!("Attempted to read uninitialized register {reg:?}."))
                     }
                 };
-                self.core_reg.insert(reg, val);
+                self.journal_reg_write(reg);
+                self.core_reg.set(reg, val);
             }
-            Ok(Value::register_value(reg, self.core_reg[&reg]))
+            Ok(Value::register_value(reg, self.core_reg.get(reg).unwrap()))
         }
     }
 
     pub fn write_register(&mut self, reg: Register, val: u32) -> Value {
-        if reg != Register::X0 {
-            self.core_reg.insert(reg, val);
-        }
-        Value::register_value(reg, val)
+        let prev = self.journal_reg_write(reg);
+        self.core_reg.set(reg, val);
+        Value::register_value(reg, val).with_prev(prev)
     }
+}
 
-    pub fn load_elf(&mut self, file: PathBuf) -> Result<(), anyhow::Error> {
-        let file_data = std::fs::read(file)?;
-        let file = ElfBytes::<LittleEndian>::minimal_parse(file_data.as_slice())?;
+// Lookup table from region page to the first region overlapping it:
+fn region_pages(mems: &[MemoryRegion]) -> Vec<u16> {
+    assert!(mems.len() < NO_REGION as usize);
 
-        if let Some(header_table) = file.section_headers() {
-            for header in header_table.into_iter() {
-                if header.sh_type == 0x1 {
-                    // sh_type == 0x1 indicates 'SHT_PROGBITS'/Program data
-                    let (section_data, _) = file.section_data(&header)?;
-                    for (offset, byte) in section_data.iter().enumerate() {
-                        self.program_b((header.sh_addr + (offset as u64)).try_into()?, *byte)?;
-                    }
-                }
+    let mut pages = vec![NO_REGION; 1 << (32 - REGION_PAGE_BITS)];
+    for (idx, region) in mems.iter().enumerate() {
+        if region.adr_range.is_empty() {
+            continue;
+        }
+        let first_page = region.adr_range.start >> REGION_PAGE_BITS;
+        let last_page = (region.adr_range.end - 1) >> REGION_PAGE_BITS;
+        for page in &mut pages[first_page as usize..=last_page as usize] {
+            if *page == NO_REGION {
+                *page = idx as u16;
             }
         }
-        Ok(())
     }
+    pages
 }
@@ -0,0 +1,513 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use elf::{
+    abi::{EF_RISCV_RVE, EM_RISCV, PT_LOAD, STT_FUNC, STT_NOTYPE, STT_OBJECT},
+    endian::LittleEndian,
+    file::Class,
+    ElfBytes,
+};
+
+use crate::{
+    symbols::{Symbol, SymbolTable},
+    DRVSim,
+};
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Load all PT_LOAD segments and symbols of an RV32E ELF file, returning its entry point. The
+    // PC is not changed, use set_pc to start execution at the entry point.
+    pub fn load_elf(&mut self, file: PathBuf) -> Result<u32, anyhow::Error> {
+        let file_data = std::fs::read(file)?;
+        self.load_elf_bytes(&file_data)
+    }
+
+    pub fn load_elf_bytes(&mut self, data: &[u8]) -> Result<u32, anyhow::Error> {
+        let file = ElfBytes::<LittleEndian>::minimal_parse(data)?;
+
+        if file.ehdr.class != Class::ELF32 {
+            return Err(anyhow!("ELF file is not a 32 bit ELF file."));
+        }
+        if file.ehdr.e_machine != EM_RISCV {
+            return Err(anyhow!(
+                "ELF file is not a RISC-V ELF file (e_machine = {}).",
+                file.ehdr.e_machine
+            ));
+        }
+        if file.ehdr.e_flags & EF_RISCV_RVE == 0 {
+            return Err(anyhow!("ELF file does not target the RV32E base ISA."));
+        }
+
+        let segments = file
+            .segments()
+            .ok_or(anyhow!("ELF file has no program headers."))?;
+
+        for phdr in segments.iter().filter(|phdr| phdr.p_type == PT_LOAD) {
+            if phdr.p_filesz > phdr.p_memsz {
+                return Err(anyhow!(
+                    "ELF segment at 0x{:x} is larger in the file than in memory.",
+                    phdr.p_paddr
+                ));
+            }
+
+            // Segments are placed at their load address. Any memory not backed by the file is
+            // zero-filled (.bss):
+            let adr: u32 = phdr.p_paddr.try_into()?;
+            let data = file.segment_data(&phdr)?;
+            for offset in 0..phdr.p_memsz {
+                let byte = data.get(offset as usize).copied().unwrap_or(0);
+                self.program_b(offset_adr(adr, offset)?, byte)?;
+            }
+        }
+
+        // Keep code and data symbols, skipping mapping symbols ('$x') and local labels. Symbols
+        // replace those of the same name from earlier loads:
+        let mut symbols = SymbolTable::new();
+        if let Some((symtab, strtab)) = file.symbol_table()? {
+            for sym in symtab.iter() {
+                if sym.is_undefined()
+                    || ![STT_NOTYPE, STT_OBJECT, STT_FUNC].contains(&sym.st_symtype())
+                {
+                    continue;
+                }
+                let name = strtab.get(sym.st_name as usize)?;
+                if name.is_empty() || name.starts_with('$') || name.starts_with(".L") {
+                    continue;
+                }
+                symbols.insert(Symbol {
+                    name: name.to_string(),
+                    adr: sym.st_value.try_into()?,
+                    size: sym.st_size.try_into()?,
+                });
+            }
+        }
+        self.symbols.merge(symbols);
+
+        Ok(file.ehdr.e_entry.try_into()?)
+    }
+
+    // Load a flat binary image to a base address.
+    pub fn load_bin(&mut self, file: PathBuf, base: u32) -> Result<(), anyhow::Error> {
+        let file_data = std::fs::read(file)?;
+        self.load_bin_bytes(&file_data, base)
+    }
+
+    pub fn load_bin_bytes(&mut self, data: &[u8], base: u32) -> Result<(), anyhow::Error> {
+        for (offset, byte) in data.iter().enumerate() {
+            self.program_b(offset_adr(base, offset as u64)?, *byte)?;
+        }
+        Ok(())
+    }
+
+    // Load an Intel HEX image, with all addresses offset by a base address. Returns the start
+    // address, if given in the file.
+    pub fn load_ihex(&mut self, file: PathBuf, base: u32) -> Result<Option<u32>, anyhow::Error> {
+        let text = std::fs::read_to_string(file)?;
+        self.load_ihex_str(&text, base)
+    }
+
+    pub fn load_ihex_str(&mut self, text: &str, base: u32) -> Result<Option<u32>, anyhow::Error> {
+        let mut upper_adr: u32 = 0; // Set by extended segment/linear address records.
+        let mut start = None;
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| anyhow!("Intel HEX line {}: {}", line_idx + 1, msg);
+
+            let record = line
+                .strip_prefix(':')
+                .ok_or_else(|| err("Record does not start with ':'."))?;
+            if !record.is_ascii() || record.len() % 2 != 0 || record.len() < 10 {
+                return Err(err("Malformed record."));
+            }
+            let bytes = (0..record.len())
+                .step_by(2)
+                .map(|idx| u8::from_str_radix(&record[idx..idx + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| err("Invalid hex digit."))?;
+
+            let len = bytes[0] as usize;
+            if bytes.len() != len + 5 {
+                return Err(err("Record length does not match byte count."));
+            }
+            if bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) != 0 {
+                return Err(err("Checksum mismatch."));
+            }
+            let adr = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+            let data = &bytes[4..4 + len];
+
+            match bytes[3] {
+                // Data:
+                0x00 => {
+                    for (offset, byte) in data.iter().enumerate() {
+                        let adr = upper_adr.wrapping_add(adr + offset as u32);
+                        self.program_b(offset_adr(base, adr as u64)?, *byte)?;
+                    }
+                }
+                // End of file:
+                0x01 => break,
+                // Extended segment address:
+                0x02 if len == 2 => {
+                    upper_adr = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4
+                }
+                // Start segment address (CS:IP):
+                0x03 if len == 4 => {
+                    let cs = u16::from_be_bytes([data[0], data[1]]) as u32;
+                    let ip = u16::from_be_bytes([data[2], data[3]]) as u32;
+                    start = Some(offset_adr(base, ((cs << 4) + ip) as u64)?);
+                }
+                // Extended linear address:
+                0x04 if len == 2 => {
+                    upper_adr = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16
+                }
+                // Start linear address:
+                0x05 if len == 4 => {
+                    let adr = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                    start = Some(offset_adr(base, adr as u64)?);
+                }
+                0x02..=0x05 => return Err(err("Invalid record length.")),
+                _ => return Err(err("Unknown record type.")),
+            }
+        }
+
+        Ok(start)
+    }
+
+    // Load a Verilog $readmemh image with words of word_bytes (1, 2 or 4) bytes, stored little
+    // endian. Addresses given with '@' count words from the base address.
+    pub fn load_readmemh(
+        &mut self,
+        file: PathBuf,
+        base: u32,
+        word_bytes: u32,
+    ) -> Result<(), anyhow::Error> {
+        let text = std::fs::read_to_string(file)?;
+        self.load_readmemh_str(&text, base, word_bytes)
+    }
+
+    pub fn load_readmemh_str(
+        &mut self,
+        text: &str,
+        base: u32,
+        word_bytes: u32,
+    ) -> Result<(), anyhow::Error> {
+        if ![1, 2, 4].contains(&word_bytes) {
+            return Err(anyhow!(
+                "Invalid $readmemh word size of {word_bytes} bytes."
+            ));
+        }
+
+        let mut word_idx: u64 = 0;
+        let mut in_block_comment = false;
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let err = |msg: String| anyhow!("$readmemh line {}: {}", line_idx + 1, msg);
+
+            // Strip comments:
+            let mut code = String::new();
+            let mut rest = line;
+            loop {
+                if in_block_comment {
+                    match rest.find("*/") {
+                        Some(idx) => {
+                            in_block_comment = false;
+                            rest = &rest[idx + 2..];
+                        }
+                        None => break,
+                    }
+                } else {
+                    let line_comment = rest.find("//");
+                    let block_comment = rest.find("/*");
+                    match (line_comment, block_comment) {
+                        (Some(l), Some(b)) if l < b => {
+                            code.push_str(&rest[..l]);
+                            break;
+                        }
+                        (_, Some(b)) => {
+                            code.push_str(&rest[..b]);
+                            code.push(' ');
+                            in_block_comment = true;
+                            rest = &rest[b + 2..];
+                        }
+                        (Some(l), None) => {
+                            code.push_str(&rest[..l]);
+                            break;
+                        }
+                        (None, None) => {
+                            code.push_str(rest);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            for token in code.split_whitespace() {
+                if let Some(adr) = token.strip_prefix('@') {
+                    word_idx = u64::from_str_radix(&adr.replace('_', ""), 16)
+                        .map_err(|_| err(format!("Invalid address '{token}'.")))?;
+                    continue;
+                }
+
+                let word = u32::from_str_radix(&token.replace('_', ""), 16)
+                    .map_err(|_| err(format!("Invalid value '{token}'.")))?;
+                if word_bytes < 4 && word >> (word_bytes * 8) != 0 {
+                    return Err(err(format!(
+                        "Value '{token}' does not fit into {word_bytes} bytes."
+                    )));
+                }
+
+                let offset = word_idx.checked_mul(word_bytes as u64).ok_or_else(|| {
+                    err(format!("Address of '{token}' exceeds the address space."))
+                })?;
+                let adr = offset_adr(base, offset)?;
+                for (offset, byte) in word.to_le_bytes()[..word_bytes as usize].iter().enumerate() {
+                    self.program_b(offset_adr(adr, offset as u64)?, *byte)?;
+                }
+                word_idx += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Address at an offset from a base address, which must not exceed the address space:
+fn offset_adr(base: u32, offset: u64) -> Result<u32, anyhow::Error> {
+    (base as u64)
+        .checked_add(offset)
+        .and_then(|adr| u32::try_from(adr).ok())
+        .ok_or_else(|| anyhow!("Image at 0x{base:x} exceeds the address space."))
+}
+
+// ==== Loader Tests ===============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{inst::Register, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator() -> DRVSim {
+        DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        })
+    }
+
+    // Segment as (vaddr, paddr, data, memsz):
+    type TestSegment = (u32, u32, Vec<u8>, u32);
+
+    // Build a minimal little-endian ELF32 executable:
+    fn build_elf(class: u8, machine: u16, flags: u32, entry: u32, segs: &[TestSegment]) -> Vec<u8> {
+        const EHDR_SIZE: u32 = 52;
+        const PHDR_SIZE: u32 = 32;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', class, 1, 1, 0];
+        elf.resize(16, 0);
+        elf.extend(2u16.to_le_bytes()); // e_type = ET_EXEC
+        elf.extend(machine.to_le_bytes());
+        elf.extend(1u32.to_le_bytes()); // e_version
+        elf.extend(entry.to_le_bytes());
+        elf.extend(EHDR_SIZE.to_le_bytes()); // e_phoff
+        elf.extend(0u32.to_le_bytes()); // e_shoff
+        elf.extend(flags.to_le_bytes());
+        elf.extend((EHDR_SIZE as u16).to_le_bytes());
+        elf.extend((PHDR_SIZE as u16).to_le_bytes());
+        elf.extend((segs.len() as u16).to_le_bytes());
+        elf.extend(40u16.to_le_bytes()); // e_shentsize
+        elf.extend(0u16.to_le_bytes()); // e_shnum
+        elf.extend(0u16.to_le_bytes()); // e_shstrndx
+
+        let mut offset = EHDR_SIZE + PHDR_SIZE * segs.len() as u32;
+        for (vaddr, paddr, data, memsz) in segs {
+            for field in [
+                1,
+                offset,
+                *vaddr,
+                *paddr,
+                data.len() as u32,
+                *memsz,
+                0x7,
+                0x4,
+            ] {
+                elf.extend(field.to_le_bytes());
+            }
+            offset += data.len() as u32;
+        }
+        for (_, _, data, _) in segs {
+            elf.extend(data);
+        }
+        elf
+    }
+
+    #[test]
+    fn load_segments() {
+        let text = [
+            0x00100093u32, // ADDI x1, x0, 1
+            0x0000006f,    // JAL x0, .+0
+        ]
+        .iter()
+        .flat_map(|inst| inst.to_le_bytes())
+        .collect();
+        let elf = build_elf(
+            1,
+            243,
+            0x8,
+            ROM_START + 0x100,
+            &[
+                (ROM_START + 0x100, ROM_START + 0x100, text, 8),
+                // .data, linked in RAM but loaded to ROM:
+                (RAM_START, ROM_START + 0x200, vec![0xAA, 0xBB], 2),
+                // .bss:
+                (RAM_START + 0x10, RAM_START + 0x10, vec![0xCC], 8),
+            ],
+        );
+
+        let mut sim = new_simulator();
+        let entry = sim.load_elf_bytes(&elf).unwrap();
+        assert_eq!(entry, ROM_START + 0x100);
+
+        assert_eq!(sim.read_h(ROM_START + 0x200).unwrap().val, 0xBBAA);
+        assert!(sim.read_b(RAM_START).is_err());
+        assert_eq!(sim.read_b(RAM_START + 0x10).unwrap().val, 0xCC);
+        for adr in RAM_START + 0x11..RAM_START + 0x18 {
+            assert_eq!(sim.read_b(adr).unwrap().val, 0);
+        }
+        assert!(sim.read_b(RAM_START + 0x18).is_err());
+
+        sim.set_pc(entry);
+        assert_eq!(sim.pc(), entry);
+        sim.step().unwrap();
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 1);
+    }
+
+    #[test]
+    fn load_validation() {
+        let mut sim = new_simulator();
+        let seg = vec![(ROM_START, ROM_START, vec![0; 4], 4)];
+
+        // ELF64, wrong machine, missing RVE flag:
+        assert!(sim
+            .load_elf_bytes(&build_elf(2, 243, 0x8, ROM_START, &seg))
+            .is_err());
+        assert!(sim
+            .load_elf_bytes(&build_elf(1, 62, 0x8, ROM_START, &seg))
+            .is_err());
+        assert!(sim
+            .load_elf_bytes(&build_elf(1, 243, 0x0, ROM_START, &seg))
+            .is_err());
+
+        // Segment outside of memory:
+        let seg = vec![(0, 0, vec![0; 4], 4)];
+        assert!(sim
+            .load_elf_bytes(&build_elf(1, 243, 0x8, ROM_START, &seg))
+            .is_err());
+
+        assert!(sim.load_elf_bytes(b"not an elf file").is_err());
+    }
+
+    #[test]
+    fn load_bin() {
+        let mut sim = new_simulator();
+        sim.load_bin_bytes(&[0x93, 0x00, 0x10, 0x00, 0xAB], ROM_START + 0x10)
+            .unwrap();
+        assert_eq!(sim.read_w(ROM_START + 0x10).unwrap().val, 0x00100093);
+        assert_eq!(sim.read_b(ROM_START + 0x14).unwrap().val, 0xAB);
+        assert!(sim.read_b(ROM_START + 0x15).is_err());
+
+        // Region checks:
+        assert!(sim.load_bin_bytes(&[0; 4], ROM_START + 0x7FFE).is_err());
+        assert!(sim.load_bin_bytes(&[0; 4], 0xFFFFFFFE).is_err());
+    }
+
+    #[test]
+    fn load_ihex() {
+        let mut sim = new_simulator();
+        let start = sim
+            .load_ihex_str(
+                concat!(
+                    ":020000040100F9\n",
+                    ":040010009300100049\n",
+                    ":020000040200F8\n",
+                    ":02000800AABB91\n",
+                    ":0400000501000100F5\n",
+                    ":00000001FF\n",
+                ),
+                0,
+            )
+            .unwrap();
+        assert_eq!(start, Some(ROM_START + 0x100));
+        assert_eq!(sim.read_w(ROM_START + 0x10).unwrap().val, 0x00100093);
+        assert_eq!(sim.read_h(RAM_START + 0x8).unwrap().val, 0xBBAA);
+
+        // Base address:
+        let start = sim
+            .load_ihex_str(":0100000042BD\n:00000001FF\n", RAM_START + 0x20)
+            .unwrap();
+        assert_eq!(start, None);
+        assert_eq!(sim.read_b(RAM_START + 0x20).unwrap().val, 0x42);
+
+        // Checksum, malformed records, region checks:
+        assert!(sim.load_ihex_str(":0100000042BE\n", RAM_START).is_err());
+        assert!(sim.load_ihex_str("0100000042BD\n", RAM_START).is_err());
+        assert!(sim.load_ihex_str(":0200000042BC\n", RAM_START).is_err());
+        assert!(sim.load_ihex_str(":0100000042BD\n", 0).is_err());
+        assert!(sim
+            .load_ihex_str(":01000000X\u{e9}2BD\n", RAM_START)
+            .is_err());
+    }
+
+    #[test]
+    fn load_readmemh() {
+        let mut sim = new_simulator();
+        sim.load_readmemh_str(
+            concat!(
+                "// Test image\n",
+                "00100093 0000_006f /* comment\n",
+                "continues */ @4\n",
+                "deadbeef // comment\n",
+            ),
+            ROM_START,
+            4,
+        )
+        .unwrap();
+        assert_eq!(sim.read_w(ROM_START).unwrap().val, 0x00100093);
+        assert_eq!(sim.read_w(ROM_START + 0x4).unwrap().val, 0x0000006f);
+        assert!(sim.read_w(ROM_START + 0x8).is_err());
+        assert_eq!(sim.read_w(ROM_START + 0x10).unwrap().val, 0xdeadbeef);
+
+        sim.load_readmemh_str("@2 ab cd\n", RAM_START, 1).unwrap();
+        assert_eq!(sim.read_h(RAM_START + 0x2).unwrap().val, 0xcdab);
+        sim.load_readmemh_str("@1 1234\n", RAM_START, 2).unwrap();
+        assert_eq!(sim.read_h(RAM_START + 0x2).unwrap().val, 0x1234);
+
+        assert!(sim.load_readmemh_str("123\n", RAM_START, 1).is_err());
+        assert!(sim.load_readmemh_str("xyz\n", RAM_START, 4).is_err());
+        assert!(sim.load_readmemh_str("00\n", RAM_START, 3).is_err());
+        assert!(sim.load_readmemh_str("@10000 00\n", RAM_START, 1).is_err());
+        assert!(sim
+            .load_readmemh_str("@ffffffffffffffff 00\n", RAM_START, 4)
+            .is_err());
+    }
+}
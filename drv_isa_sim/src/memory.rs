@@ -1,71 +1,126 @@
 use anyhow::anyhow;
-use std::collections::HashMap;
+use std::ops::Range;
 
 use rand::Rng;
 
-use crate::ValueInit;
+use crate::{inst::Instruction, AccessFault, ValueInit};
 
 // ==== Type/Constant Definitions ==================================================================
 
-const BLOCK_SIZE: u32 = 0x100;
+// Granularity of snapshots:
+pub(crate) const BLOCK_SIZE: u32 = 0x100;
 
+// Instructions per page of the predecode cache:
+const ICACHE_PAGE_SIZE: usize = 0x400;
+
+// Predecoded instructions, together with their instruction word:
+type ICachePage = Box<[Option<(Instruction, u32)>; ICACHE_PAGE_SIZE]>;
+
+// Dense memory buffer, with a bitmap marking which bytes have been initialized. The buffer is
+// zero-allocated, so pages of large regions that are never written stay unmapped on most hosts.
+#[derive(Clone)]
 pub struct Memory {
     pub start_adr: u32,
     write_protected: bool,
     init: ValueInit,
-    mem: HashMap<u32, [Option<u8>; BLOCK_SIZE as usize]>,
+    data: Vec<u8>,                   // Contents, zero if uninitialized.
+    init_map: Vec<u64>,              // One bit per byte, set if initialized.
+    icache: Vec<Option<ICachePage>>, // Predecoded instructions per word, allocated on demand.
 }
 
 // ==== Memory Implementation ======================================================================
 
 impl Memory {
-    pub fn new(start_adr: u32, init: ValueInit, write_protected: bool) -> Memory {
+    pub fn new(adr_range: Range<u32>, init: ValueInit, write_protected: bool) -> Memory {
+        let len = adr_range.len();
         Memory {
-            start_adr,
+            start_adr: adr_range.start,
             write_protected,
             init,
-            mem: HashMap::new(),
+            data: vec![0; len],
+            init_map: vec![0; len.div_ceil(64)],
+            icache: vec![None; len.div_ceil(4 * ICACHE_PAGE_SIZE)],
         }
     }
 
-    pub fn read_b(&mut self, adr: u32) -> Result<u8, anyhow::Error> {
+    // Offset of an address into the buffer:
+    fn offset(&self, adr: u32) -> usize {
         assert!(adr >= self.start_adr);
+        let offset = (adr - self.start_adr) as usize;
+        assert!(offset < self.data.len());
+        offset
+    }
 
-        let adr_abs = adr;
-        let adr = adr - self.start_adr;
-        let block = adr / BLOCK_SIZE;
+    fn is_init(&self, offset: usize) -> bool {
+        self.init_map[offset / 64] & (1 << (offset % 64)) != 0
+    }
 
-        self.mem.entry(block).or_insert([None; BLOCK_SIZE as usize]);
+    // Mark a byte as (un)initialized after its contents changed:
+    fn set_init(&mut self, offset: usize, init: bool) {
+        self.invalidate_inst(offset);
+        if init {
+            self.init_map[offset / 64] |= 1 << (offset % 64);
+        } else {
+            self.init_map[offset / 64] &= !(1 << (offset % 64));
+        }
+    }
 
-        if let Some(val) = self.mem[&block][(adr % BLOCK_SIZE) as usize] {
-            Ok(val)
+    // Check if all bytes of an access are initialized:
+    fn is_init_range(&self, offset: usize, len: usize) -> bool {
+        let bit = offset % 64;
+        if bit + len <= 64 {
+            let mask = ((1u64 << len) - 1) << bit;
+            self.init_map[offset / 64] & mask == mask
         } else {
-            let init_val: u8 = match self.init {
+            (offset..offset + len).all(|offset| self.is_init(offset))
+        }
+    }
+
+    // Read a byte. Uninitialized bytes are initialized on first read, so repeated reads return
+    // the same value.
+    pub fn read_b(&mut self, adr: u32) -> Result<u8, anyhow::Error> {
+        let offset = self.offset(adr);
+
+        if !self.is_init(offset) {
+            let init_val = match self.init {
                 ValueInit::Random => rand::thread_rng().gen(),
                 ValueInit::Zero => 0,
                 ValueInit::Ones => 0xFF,
                 ValueInit::FixedByte(b) => b,
-                ValueInit::FixedWord(w) => ((w >> (8 * (adr_abs % 4))) & 0xff).try_into().unwrap(),
+                ValueInit::FixedWord(w) => ((w >> (8 * (adr % 4))) & 0xff) as u8,
                 ValueInit::Error => {
                     return Err(anyhow!(
                         "Attempted to read uninitialized memory at 0x{adr:x}."
                     ))
                 }
             };
-
-            self.mem.get_mut(&block).unwrap()[(adr % BLOCK_SIZE) as usize] = None;
-
-            Ok(init_val)
+            self.program_b(adr, init_val);
         }
+
+        Ok(self.data[offset])
     }
 
     pub fn read_h(&mut self, adr: u32) -> Result<u16, anyhow::Error> {
+        let offset = self.offset(adr);
+        if self.is_init_range(offset, 2) {
+            return Ok(u16::from_le_bytes([
+                self.data[offset],
+                self.data[offset + 1],
+            ]));
+        }
+
         let b0 = self.read_b(adr)? as u16;
         let b1 = self.read_b(adr + 1)? as u16;
         Ok((b1 << 8) | (b0))
     }
 
     pub fn read_w(&mut self, adr: u32) -> Result<u32, anyhow::Error> {
+        let offset = self.offset(adr);
+        if self.is_init_range(offset, 4) {
+            let bytes = self.data[offset..offset + 4].try_into().unwrap();
+            return Ok(u32::from_le_bytes(bytes));
+        }
+
         let b0 = self.read_b(adr)? as u32;
         let b1 = self.read_b(adr + 1)? as u32;
         let b2 = self.read_b(adr + 2)? as u32;
@@ -74,20 +129,23 @@ impl Memory {
     }
 
     pub fn program_b(&mut self, adr: u32, val: u8) {
-        assert!(adr >= self.start_adr);
-
-        let adr = adr - self.start_adr;
-
-        let block = adr / BLOCK_SIZE;
-
-        self.mem.entry(block).or_insert([None; BLOCK_SIZE as usize]);
+        let offset = self.offset(adr);
+        self.data[offset] = val;
+        self.set_init(offset, true);
+    }
 
-        self.mem.get_mut(&block).unwrap()[(adr % BLOCK_SIZE) as usize] = Some(val);
+    // Write bytes, ignoring write protection:
+    fn program(&mut self, adr: u32, bytes: &[u8]) {
+        let offset = self.offset(adr);
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        for offset in offset..offset + bytes.len() {
+            self.set_init(offset, true);
+        }
     }
 
     pub fn write_b(&mut self, adr: u32, val: u8) -> Result<(), anyhow::Error> {
         if self.write_protected {
-            return Err(anyhow!("Attempted to write to read-only memory!"));
+            return Err(AccessFault::WriteProtected { adr }.into());
         }
 
         self.program_b(adr, val);
@@ -95,29 +153,116 @@ impl Memory {
     }
 
     pub fn write_h(&mut self, adr: u32, val: u16) -> Result<(), anyhow::Error> {
-        self.write_b(adr, (val & 0xff).try_into().unwrap())?;
-        self.write_b(adr + 1, ((val >> 8) & 0xff).try_into().unwrap())?;
+        if self.write_protected {
+            return Err(AccessFault::WriteProtected { adr }.into());
+        }
+
+        self.program(adr, &val.to_le_bytes());
         Ok(())
     }
 
     pub fn write_w(&mut self, adr: u32, val: u32) -> Result<(), anyhow::Error> {
-        self.write_b(adr, (val & 0xff).try_into().unwrap())?;
-        self.write_b(adr + 1, ((val >> 8) & 0xff).try_into().unwrap())?;
-        self.write_b(adr + 2, ((val >> 16) & 0xff).try_into().unwrap())?;
-        self.write_b(adr + 3, ((val >> 24) & 0xff).try_into().unwrap())?;
+        if self.write_protected {
+            return Err(AccessFault::WriteProtected { adr }.into());
+        }
+
+        self.program(adr, &val.to_le_bytes());
         Ok(())
     }
+
+    // Contents of a byte, without initializing it:
+    pub fn peek_b(&self, adr: u32) -> Option<u8> {
+        let offset = self.offset(adr);
+        self.is_init(offset).then_some(self.data[offset])
+    }
+
+    // Contents of a halfword, without initializing it. None if any byte is uninitialized.
+    pub fn peek_h(&self, adr: u32) -> Option<u16> {
+        let offset = self.offset(adr);
+        self.is_init_range(offset, 2)
+            .then(|| u16::from_le_bytes([self.data[offset], self.data[offset + 1]]))
+    }
+
+    // Contents of a word, without initializing it. None if any byte is uninitialized.
+    pub fn peek_w(&self, adr: u32) -> Option<u32> {
+        let offset = self.offset(adr);
+        self.is_init_range(offset, 4)
+            .then(|| u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap()))
+    }
+
+    // Set the contents of a byte, including marking it uninitialized:
+    pub fn restore_b(&mut self, adr: u32, val: Option<u8>) {
+        let offset = self.offset(adr);
+        self.data[offset] = val.unwrap_or(0);
+        self.set_init(offset, val.is_some());
+    }
+
+    // Predecoded instruction and instruction word at a word-aligned address, if cached:
+    pub fn cached_inst(&self, adr: u32) -> Option<(Instruction, u32)> {
+        let word = self.offset(adr) / 4;
+        self.icache[word / ICACHE_PAGE_SIZE].as_ref()?[word % ICACHE_PAGE_SIZE]
+    }
+
+    pub fn cache_inst(&mut self, adr: u32, inst: (Instruction, u32)) {
+        let word = self.offset(adr) / 4;
+        let page = self.icache[word / ICACHE_PAGE_SIZE]
+            .get_or_insert_with(|| Box::new([None; ICACHE_PAGE_SIZE]));
+        page[word % ICACHE_PAGE_SIZE] = Some(inst);
+    }
+
+    // Drop the predecoded instruction containing a byte:
+    fn invalidate_inst(&mut self, offset: usize) {
+        let word = offset / 4;
+        if let Some(page) = &mut self.icache[word / ICACHE_PAGE_SIZE] {
+            page[word % ICACHE_PAGE_SIZE] = None;
+        }
+    }
+
+    // Blocks containing initialized bytes, sorted by block index:
+    pub fn blocks(&self) -> Vec<(u32, [Option<u8>; BLOCK_SIZE as usize])> {
+        const WORDS_PER_BLOCK: usize = (BLOCK_SIZE / 64) as usize;
+
+        let mut blocks = vec![];
+        for (idx, init) in self.init_map.chunks(WORDS_PER_BLOCK).enumerate() {
+            if init.iter().all(|word| *word == 0) {
+                continue;
+            }
+            let start = idx * BLOCK_SIZE as usize;
+            let mut block = [None; BLOCK_SIZE as usize];
+            for (offset, val) in block.iter_mut().enumerate() {
+                let offset = start + offset;
+                if offset < self.data.len() && self.is_init(offset) {
+                    *val = Some(self.data[offset]);
+                }
+            }
+            blocks.push((idx as u32, block));
+        }
+        blocks
+    }
+
+    // Replace a block. Bytes past the end of the memory are ignored.
+    pub fn insert_block(&mut self, idx: u32, block: [Option<u8>; BLOCK_SIZE as usize]) {
+        let start = idx as usize * BLOCK_SIZE as usize;
+        for (offset, val) in block.iter().enumerate() {
+            let offset = start + offset;
+            if offset < self.data.len() {
+                self.data[offset] = val.unwrap_or(0);
+                self.set_init(offset, val.is_some());
+            }
+        }
+    }
 }
 
 // ==== Memory Tests ===============================================================================
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod tests {
     use crate::memory::*;
 
     #[test]
     fn memory_write_read() {
-        let mut mem = Memory::new(0xABC, ValueInit::Error, false);
+        let mut mem = Memory::new(0xABC..0x1ABC, ValueInit::Error, false);
         mem.write_w(0xABC, 0xA1B2C3D4).unwrap();
         assert_eq!(mem.read_b(0xABC).unwrap(), 0xD4);
         assert_eq!(mem.read_b(0xABC + 1).unwrap(), 0xC3);
@@ -135,42 +280,127 @@ mod tests {
     #[test]
     #[should_panic]
     fn memory_acc_before_start() {
-        let mut mem = Memory::new(0xABC, ValueInit::Error, false);
+        let mut mem = Memory::new(0xABC..0x1ABC, ValueInit::Error, false);
         mem.write_w(0xABC - 1, 0).unwrap();
     }
 
     #[test]
     #[should_panic]
     fn memory_acc_uninit_error() {
-        let mut mem = Memory::new(0xABC, ValueInit::Error, false);
+        let mut mem = Memory::new(0xABC..0x1ABC, ValueInit::Error, false);
         let _ = mem.read_b(0xABC).unwrap();
     }
 
     #[test]
     #[should_panic]
     fn memory_write_protection() {
-        let mut mem = Memory::new(0xABC, ValueInit::Error, true);
+        let mut mem = Memory::new(0xABC..0x1ABC, ValueInit::Error, true);
         mem.write_b(0xABC, 0x0).unwrap();
     }
 
     #[test]
     fn memory_acc_fixed() {
-        let mut mem = Memory::new(0, ValueInit::Zero, false);
+        let mut mem = Memory::new(0..0x100, ValueInit::Zero, false);
         assert_eq!(mem.read_b(0).unwrap(), 0);
 
-        let mut mem = Memory::new(0, ValueInit::Ones, false);
+        let mut mem = Memory::new(0..0x100, ValueInit::Ones, false);
         assert_eq!(mem.read_b(0).unwrap(), 0xFF);
 
-        let mut mem = Memory::new(0, ValueInit::FixedByte(0xAB), false);
+        let mut mem = Memory::new(0..0x100, ValueInit::FixedByte(0xAB), false);
         assert_eq!(mem.read_b(0).unwrap(), 0xAB);
         assert_eq!(mem.read_b(1).unwrap(), 0xAB);
         assert_eq!(mem.read_b(2).unwrap(), 0xAB);
         assert_eq!(mem.read_b(3).unwrap(), 0xAB);
 
-        let mut mem = Memory::new(0x2, ValueInit::FixedWord(0xDEADBEEF), false);
+        let mut mem = Memory::new(0x2..0x102, ValueInit::FixedWord(0xDEADBEEF), false);
         assert_eq!(mem.read_b(0x4).unwrap(), 0xEF);
         assert_eq!(mem.read_b(0x5).unwrap(), 0xBE);
         assert_eq!(mem.read_b(0x6).unwrap(), 0xAD);
         assert_eq!(mem.read_b(0x7).unwrap(), 0xDE);
     }
+
+    #[test]
+    fn memory_blocks() {
+        let mut mem = Memory::new(
+            0x100..0x100 + 3 * BLOCK_SIZE + 0x10,
+            ValueInit::Error,
+            false,
+        );
+        assert!(mem.blocks().is_empty());
+
+        mem.write_w(0x100 + 3 * BLOCK_SIZE + 0xC, 0xDEADBEEF)
+            .unwrap();
+        mem.write_b(0x100 + 0x3F, 0xAB).unwrap();
+        assert_eq!(mem.peek_b(0x100 + 0x3F), Some(0xAB));
+        assert_eq!(mem.peek_b(0x100 + 0x40), None);
+
+        let blocks = mem.blocks();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].0, 0);
+        assert_eq!(blocks[0].1[0x3F], Some(0xAB));
+        assert_eq!(blocks[1].0, 3);
+        assert_eq!(blocks[1].1[0xF], Some(0xDE));
+        assert_eq!(blocks[1].1[0x10], None);
+
+        let mut other = Memory::new(
+            0x100..0x100 + 3 * BLOCK_SIZE + 0x10,
+            ValueInit::Error,
+            false,
+        );
+        for (idx, block) in blocks {
+            other.insert_block(idx, block);
+        }
+        assert_eq!(
+            other.read_w(0x100 + 3 * BLOCK_SIZE + 0xC).unwrap(),
+            0xDEADBEEF
+        );
+
+        other.restore_b(0x100 + 3 * BLOCK_SIZE + 0xC, None);
+        assert!(other.read_w(0x100 + 3 * BLOCK_SIZE + 0xC).is_err());
+        assert_eq!(other.read_h(0x100 + 3 * BLOCK_SIZE + 0xE).unwrap(), 0xDEAD);
+    }
+
+    #[test]
+    fn memory_sticky_init() {
+        let mut mem = Memory::new(0..0x100, ValueInit::Random, false);
+        assert_eq!(mem.peek_b(0x10), None);
+        assert_eq!(mem.peek_w(0x10), None);
+
+        let val = mem.read_w(0x10).unwrap();
+        assert_eq!(mem.read_w(0x10).unwrap(), val);
+        assert_eq!(mem.peek_w(0x10), Some(val));
+        assert_eq!(mem.peek_h(0x12), Some((val >> 16) as u16));
+        assert_eq!(mem.peek_h(0x13), None);
+    }
+
+    #[test]
+    fn memory_inst_cache() {
+        let mut mem = Memory::new(0x100..0x2100, ValueInit::Error, true);
+        let inst = (
+            Instruction::ADDI {
+                rd: crate::inst::Register::X1,
+                rs1: crate::inst::Register::X0,
+                imm: 1,
+            },
+            0x00100093,
+        );
+        assert_eq!(mem.cached_inst(0x1100), None);
+        mem.cache_inst(0x1100, inst);
+        assert_eq!(mem.cached_inst(0x1100), Some(inst));
+
+        // Any modification of the word drops it:
+        mem.program_b(0x1103, 0x00);
+        assert_eq!(mem.cached_inst(0x1100), None);
+        mem.cache_inst(0x1100, inst);
+        mem.restore_b(0x1101, None);
+        assert_eq!(mem.cached_inst(0x1100), None);
+        mem.cache_inst(0x1100, inst);
+        mem.insert_block(0x10, [None; BLOCK_SIZE as usize]);
+        assert_eq!(mem.cached_inst(0x1100), None);
+
+        // Failed writes do not:
+        mem.cache_inst(0x1100, inst);
+        assert!(mem.write_w(0x1100, 0).is_err());
+        assert_eq!(mem.cached_inst(0x1100), Some(inst));
+    }
 }
@@ -0,0 +1,156 @@
+use crate::{inst::Register, DRVSim};
+
+// ==== Type/Constant Definitions ==================================================================
+
+// Architectural registers: x0-x15, Xmpc and Xdpc.
+pub const REG_COUNT: usize = 18;
+
+// Core register file, tracking which registers have been written since reset. x0 is never
+// stored.
+#[derive(Clone, Default)]
+pub(crate) struct RegisterFile {
+    vals: [u32; REG_COUNT],
+    init: u32, // Bit n set if register n is initialized.
+}
+
+// Contents of all architectural registers, indexed by register number. None if uninitialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterDump {
+    pub vals: [Option<u32>; REG_COUNT],
+}
+
+// ==== RegisterFile Implementation ================================================================
+
+impl RegisterFile {
+    pub fn get(&self, reg: Register) -> Option<u32> {
+        let idx = reg as usize;
+        (self.init & (1 << idx) != 0).then_some(self.vals[idx])
+    }
+
+    pub fn set(&mut self, reg: Register, val: u32) {
+        if reg != Register::X0 {
+            self.vals[reg as usize] = val;
+            self.init |= 1 << reg as usize;
+        }
+    }
+
+    // Mark a register as uninitialized:
+    pub fn clear(&mut self, reg: Register) {
+        self.vals[reg as usize] = 0;
+        self.init &= !(1 << reg as usize);
+    }
+
+    // Initialized registers and their values, sorted by register number:
+    pub fn iter(&self) -> impl Iterator<Item = (Register, u32)> + '_ {
+        (0..REG_COUNT as u32)
+            .map(|idx| Register::new(idx).unwrap())
+            .filter_map(|reg| self.get(reg).map(|val| (reg, val)))
+    }
+}
+
+// ==== RegisterDump Implementation ================================================================
+
+impl RegisterDump {
+    pub fn get(&self, reg: Register) -> Option<u32> {
+        self.vals[reg as usize]
+    }
+
+    // Registers that have not been initialized:
+    pub fn uninitialized(&self) -> Vec<Register> {
+        (0..REG_COUNT as u32)
+            .map(|idx| Register::new(idx).unwrap())
+            .filter(|reg| self.get(*reg).is_none())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for RegisterDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, val) in self.vals.iter().enumerate() {
+            let reg = format!("{:?}", Register::new(idx as u32).unwrap());
+            if idx != 0 {
+                writeln!(f)?;
+            }
+            match val {
+                Some(val) => write!(f, "{:>4} = 0x{:08x}", reg, val)?,
+                None => write!(f, "{:>4} = uninit", reg)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Contents of all architectural registers, without initializing them.
+    pub fn dump_registers(&self) -> RegisterDump {
+        let mut vals = [None; REG_COUNT];
+        for (idx, val) in vals.iter_mut().enumerate() {
+            *val = self.peek_register(Register::new(idx as u32).unwrap());
+        }
+        RegisterDump { vals }
+    }
+}
+
+// ==== Register Tests =============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{inst::Register, registers::*, *};
+
+    const ROM_START: u32 = 0x1000000;
+
+    #[test]
+    fn register_file() {
+        let mut regs = RegisterFile::default();
+        assert_eq!(regs.get(Register::X1), None);
+
+        regs.set(Register::X1, 0);
+        regs.set(Register::Xdpc, 0xABCD);
+        regs.set(Register::X0, 0x1234);
+        assert_eq!(regs.get(Register::X1), Some(0));
+        assert_eq!(regs.get(Register::Xdpc), Some(0xABCD));
+        assert_eq!(regs.get(Register::X0), None);
+        assert_eq!(
+            regs.iter().collect::<Vec<_>>(),
+            vec![(Register::X1, 0), (Register::Xdpc, 0xABCD)]
+        );
+
+        regs.clear(Register::X1);
+        assert_eq!(regs.get(Register::X1), None);
+    }
+
+    #[test]
+    fn register_dump() {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![],
+            reg_init: ValueInit::Random,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        sim.write_register(Register::X2, 0x1234);
+        sim.write_register(Register::Xmpc, 0x5678);
+
+        let dump = sim.dump_registers();
+        assert_eq!(dump.get(Register::X0), Some(0));
+        assert_eq!(dump.get(Register::X2), Some(0x1234));
+        assert_eq!(dump.get(Register::Xmpc), Some(0x5678));
+        assert_eq!(dump.uninitialized().len(), REG_COUNT - 3);
+        assert!(!dump.uninitialized().contains(&Register::X2));
+
+        // Dumping has no side effects, but reads initialize registers:
+        assert_eq!(sim.dump_registers(), dump);
+        let val = sim.read_register(Register::X1).unwrap().val;
+        assert_eq!(sim.dump_registers().get(Register::X1), Some(val));
+
+        let text = dump.to_string();
+        assert_eq!(text.lines().count(), REG_COUNT);
+        assert!(text.contains("  X2 = 0x00001234"));
+        assert!(text.contains("  X1 = uninit"));
+        assert!(text.contains("Xmpc = 0x00005678"));
+    }
+}
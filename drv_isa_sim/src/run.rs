@@ -0,0 +1,217 @@
+use crate::{breakpoint::BreakpointHit, inst_log::InstLog, DRVSim};
+
+// ==== Constants ==================================================================================
+
+const EBREAK_WORD: u32 = 0x00100073;
+
+// ==== Type Definitions ===========================================================================
+
+#[derive(Debug)]
+pub enum HaltReason {
+    StepLimit,                 // Step budget exhausted.
+    PcReached(u32),            // PC reached the requested address.
+    Predicate,                 // Stop condition was met.
+    SelfLoop(u32),             // Instruction at the given address jumped to itself.
+    Ebreak(u32),               // EBREAK reached at the given address, before taking the trap.
+    Breakpoint(BreakpointHit), // Breakpoint or watchpoint triggered.
+    StartOfHistory,            // Undo journal exhausted while stepping backwards.
+    Error(anyhow::Error),      // Simulation error.
+}
+
+// ==== Run Control Implementation =================================================================
+
+impl DRVSim {
+    // Run for at most max_steps instructions.
+    pub fn run(&mut self, max_steps: u64) -> HaltReason {
+        self.run_loop(max_steps, |_, _| None)
+    }
+
+    // Run until the PC reaches adr, for at most max_steps instructions.
+    pub fn run_to(&mut self, adr: u32, max_steps: u64) -> HaltReason {
+        self.run_loop(max_steps, |sim, _| {
+            if sim.pc == adr {
+                Some(HaltReason::PcReached(adr))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Run until the stop condition returns true for the log of an executed instruction, for at
+    // most max_steps instructions. The stop condition sees the log of every instruction, and can
+    // be used to collect a trace.
+    pub fn run_until(
+        &mut self,
+        max_steps: u64,
+        mut until: impl FnMut(&InstLog) -> bool,
+    ) -> HaltReason {
+        self.run_loop(max_steps, |_, log| {
+            if until(log) {
+                Some(HaltReason::Predicate)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Step until an error occurs, an EBREAK is reached, an instruction jumps to itself, a
+    // breakpoint is hit, or the given check halts the simulation.
+    // If the previous run halted at a breakpoint or EBREAK and the PC has not changed since, it is
+    // skipped so that the run can be resumed. Resuming from an EBREAK enters the trap handler.
+    fn run_loop(
+        &mut self,
+        max_steps: u64,
+        mut check: impl FnMut(&DRVSim, &InstLog) -> Option<HaltReason>,
+    ) -> HaltReason {
+        for _ in 0..max_steps {
+            if self.breakpoints.resume_pc != Some(self.pc) {
+                if let Some(id) = self.breakpoints.pc_breakpoint(self.pc) {
+                    self.breakpoints.resume_pc = Some(self.pc);
+                    return HaltReason::Breakpoint(BreakpointHit {
+                        id,
+                        pc: self.pc,
+                        access: None,
+                    });
+                }
+                if self.at_ebreak() {
+                    self.breakpoints.resume_pc = Some(self.pc);
+                    return HaltReason::Ebreak(self.pc);
+                }
+            }
+
+            self.breakpoints.hit = None;
+            let log = match self.step() {
+                Ok(log) => log,
+                Err(err) => return HaltReason::Error(err),
+            };
+
+            if let Some(hit) = self.breakpoints.hit.take() {
+                return HaltReason::Breakpoint(hit);
+            }
+
+            if let Some(reason) = check(self, &log) {
+                return reason;
+            }
+
+            if log.trap.is_none() && log.branching == Some(log.pc) {
+                // Programs end in a tight 'j .' loop:
+                return HaltReason::SelfLoop(log.pc);
+            }
+        }
+
+        HaltReason::StepLimit
+    }
+
+    // Check if the next step executes an EBREAK, instead of entering debug mode or taking an
+    // interrupt. Compares the raw instruction word, using the predecoded one if available:
+    fn at_ebreak(&self) -> bool {
+        let word = match self.cached_inst(self.pc) {
+            Some((_, word)) => Some(word),
+            None => self.peek_w(self.pc).ok().flatten(),
+        };
+        word == Some(EBREAK_WORD)
+            && (!self.dbg_req || self.debug_mode)
+            && self.pending_interrupt().is_none()
+    }
+}
+
+// ==== Run Control Tests ==========================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{inst::Register, run::*, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const MTVEC: u32 = ROM_START + 0x4000;
+
+    fn new_simulator(insts: Vec<u32>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: MTVEC,
+            dvec: 0,
+            mem_regions: vec![MemoryRegionConfig {
+                adr_range: ROM_START..ROM_START + 0x8000,
+                init: ValueInit::Error,
+                region_type: MemoryRegionType::ROM,
+            }],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    #[test]
+    fn run_self_loop() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0x00108093, // ADDI x1, x1, 1
+            0x0000006f, // JAL x0, .+0
+        ]);
+        let reason = sim.run(100);
+        assert!(matches!(reason, HaltReason::SelfLoop(adr) if adr == ROM_START + 8));
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+    }
+
+    #[test]
+    fn run_step_limit() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0xffdff06f, // JAL x0, .-4
+        ]);
+        let reason = sim.run(10);
+        assert!(matches!(reason, HaltReason::StepLimit));
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 5);
+    }
+
+    #[test]
+    fn run_to_pc() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0x00108093, // ADDI x1, x1, 1
+            0x00108093, // ADDI x1, x1, 1
+        ]);
+        let reason = sim.run_to(ROM_START + 8, 100);
+        assert!(matches!(reason, HaltReason::PcReached(adr) if adr == ROM_START + 8));
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 2);
+    }
+
+    #[test]
+    fn run_until_predicate() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0x00100113, // ADDI x2, x0, 1
+            0x00108093, // ADDI x1, x1, 1
+        ]);
+        let mut trace = vec![];
+        let reason = sim.run_until(100, |log| {
+            trace.push(log.to_log_string());
+            log.commit_values
+                .iter()
+                .any(|val| val.val == 1 && log.pc != ROM_START)
+        });
+        assert!(matches!(reason, HaltReason::Predicate));
+        assert_eq!(trace.len(), 2);
+    }
+
+    #[test]
+    fn run_ebreak_and_error() {
+        let mut sim = new_simulator(vec![
+            0x00108093, // ADDI x1, x1, 1
+            0x00100073, // EBREAK
+        ]);
+        let reason = sim.run(100);
+        assert!(matches!(reason, HaltReason::Ebreak(adr) if adr == ROM_START + 4));
+        assert_eq!(sim.pc, ROM_START + 4);
+        assert!(!sim.handling_trap);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 1);
+
+        // Resuming takes the trap, but there is nothing at mtvec:
+        let reason = sim.run(100);
+        assert!(matches!(reason, HaltReason::Error(_)));
+        assert!(sim.handling_trap);
+    }
+}
@@ -0,0 +1,569 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+use crate::{
+    csr::CsrFile,
+    inst::Register,
+    memory::BLOCK_SIZE,
+    region_pages,
+    registers::RegisterFile,
+    symbols::{Symbol, SymbolTable},
+    DRVSim, DRVSimConfig, FaultMode, MemoryRegion, MemoryRegionConfig, MemoryRegionType,
+    RegionBacking, ValueInit,
+};
+
+// ==== Constants ==================================================================================
+
+const MAGIC: &[u8; 8] = b"DRVSNAP\0";
+const VERSION: u32 = 1;
+
+// ==== Type Definitions ===========================================================================
+
+// Complete architectural state of a simulator, including its configuration, memory contents and
+// symbols. Breakpoints and watchpoints are not part of a snapshot. Devices are shared with the
+// simulator, and their internal state is not captured, only the interrupt lines they last
+// asserted. Snapshots containing devices cannot be serialized.
+#[derive(Clone)]
+pub struct Snapshot {
+    core_reg: RegisterFile,
+    pc: u32,
+    handling_trap: bool,
+    debug_mode: bool,
+    dbg_req: bool,
+    irq_pending: u32,
+    device_irq: u32,
+    irq_enable: u32,
+    irq_global_enable: bool,
+    csr: CsrFile,
+    mems: Vec<MemoryRegion>,
+    symbols: SymbolTable,
+    config: DRVSimConfig,
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            core_reg: self.core_reg.clone(),
+            pc: self.pc,
+            handling_trap: self.handling_trap,
+            debug_mode: self.debug_mode,
+            dbg_req: self.dbg_req,
+            irq_pending: self.irq_pending,
+            device_irq: self.device_irq,
+            irq_enable: self.irq_enable,
+            irq_global_enable: self.irq_global_enable,
+            csr: self.csr.clone(),
+            mems: self.mems.clone(),
+            symbols: self.symbols.clone(),
+            config: self.config.clone(),
+        }
+    }
+
+    // Return to the state captured in a snapshot. Breakpoints and watchpoints are kept, the undo
+    // journal is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.core_reg = snapshot.core_reg.clone();
+        self.pc = snapshot.pc;
+        self.breakpoints.resume_pc = None;
+        self.handling_trap = snapshot.handling_trap;
+        self.debug_mode = snapshot.debug_mode;
+        self.dbg_req = snapshot.dbg_req;
+        self.irq_pending = snapshot.irq_pending;
+        self.device_irq = snapshot.device_irq;
+        self.irq_enable = snapshot.irq_enable;
+        self.irq_global_enable = snapshot.irq_global_enable;
+        self.csr = snapshot.csr.clone();
+        self.mems = snapshot.mems.clone();
+        self.region_pages = region_pages(&self.mems);
+        self.symbols = snapshot.symbols.clone();
+        self.config = snapshot.config.clone();
+        self.undo.clear();
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> DRVSim {
+        let mut sim = DRVSim::new(snapshot.config.clone());
+        sim.restore(snapshot);
+        sim
+    }
+}
+
+// ==== Snapshot Serialization =====================================================================
+
+// All values are stored little-endian:
+//
+//   magic "DRVSNAP\0", format version (u32)
+//   config: entry, mtvec, dvec, reg_init, fault_mode, zicsr, region count,
+//           per region: start, end, init, type
+//   core state: pc, flags, irq_pending, device_irq, irq_enable
+//   csrs: mtvec, mscratch, mcause, mtval, mpie, mcycle (u64), minstret (u64)
+//   registers: count, per register: index (u8), value
+//   memories: per region: block count,
+//             per block: block index, initialized bitmap (32 bytes), data (256 bytes)
+//   symbols: count, per symbol: address, size, name length, name (UTF-8)
+//
+// Value inits are stored as a tag byte followed by a u32 argument. Blocks are sorted by index
+// so that identical states serialize identically.
+
+impl Snapshot {
+    pub fn save(&self, file: PathBuf) -> Result<(), anyhow::Error> {
+        std::fs::write(file, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn load(file: PathBuf) -> Result<Snapshot, anyhow::Error> {
+        let data = std::fs::read(file)?;
+        Snapshot::from_bytes(&data)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut w = SnapshotWriter { data: vec![] };
+        w.data.extend_from_slice(MAGIC);
+        w.u32(VERSION);
+
+        // Config:
+        w.u32(self.config.entry);
+        w.u32(self.config.mtvec);
+        w.u32(self.config.dvec);
+        w.value_init(self.config.reg_init);
+        w.u8(match self.config.fault_mode {
+            FaultMode::Error => 0,
+            FaultMode::Trap => 1,
+        });
+        w.bool(self.config.zicsr);
+        w.u32(self.config.mem_regions.len() as u32);
+        for region in self.config.mem_regions.iter() {
+            w.u32(region.adr_range.start);
+            w.u32(region.adr_range.end);
+            w.value_init(region.init);
+            w.u8(match region.region_type {
+                MemoryRegionType::RAM => 0,
+                MemoryRegionType::ROM => 1,
+                MemoryRegionType::Device(_) => {
+                    return Err(anyhow!(
+                        "Cannot serialize device region at 0x{:08x}.",
+                        region.adr_range.start
+                    ))
+                }
+            });
+        }
+
+        // Core state:
+        w.u32(self.pc);
+        w.u8((self.handling_trap as u8)
+            | ((self.debug_mode as u8) << 1)
+            | ((self.dbg_req as u8) << 2)
+            | ((self.irq_global_enable as u8) << 3));
+        w.u32(self.irq_pending);
+        w.u32(self.device_irq);
+        w.u32(self.irq_enable);
+
+        // CSRs:
+        w.u32(self.csr.mtvec);
+        w.u32(self.csr.mscratch);
+        w.u32(self.csr.mcause);
+        w.u32(self.csr.mtval);
+        w.bool(self.csr.mpie);
+        w.u64(self.csr.mcycle);
+        w.u64(self.csr.minstret);
+
+        // Registers:
+        let regs: Vec<(Register, u32)> = self.core_reg.iter().collect();
+        w.u32(regs.len() as u32);
+        for (reg, val) in regs {
+            w.u8(reg as u8);
+            w.u32(val);
+        }
+
+        // Memories:
+        for region in self.mems.iter() {
+            let RegionBacking::Memory(mem) = &region.backing else {
+                unreachable!(); // Device regions rejected above.
+            };
+            let blocks = mem.blocks();
+            w.u32(blocks.len() as u32);
+            for (block_idx, block) in blocks {
+                w.u32(block_idx);
+                let mut bitmap = [0u8; (BLOCK_SIZE / 8) as usize];
+                for (idx, val) in block.iter().enumerate() {
+                    if val.is_some() {
+                        bitmap[idx / 8] |= 1 << (idx % 8);
+                    }
+                }
+                w.data.extend_from_slice(&bitmap);
+                w.data.extend(block.iter().map(|val| val.unwrap_or(0)));
+            }
+        }
+
+        // Symbols:
+        w.u32(self.symbols.iter().count() as u32);
+        for symbol in self.symbols.iter() {
+            w.u32(symbol.adr);
+            w.u32(symbol.size);
+            w.u32(symbol.name.len() as u32);
+            w.data.extend_from_slice(symbol.name.as_bytes());
+        }
+
+        Ok(w.data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Snapshot, anyhow::Error> {
+        let mut r = SnapshotReader { data, pos: 0 };
+
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("Not a DRV simulator snapshot."));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(anyhow!("Unsupported snapshot version {version}."));
+        }
+
+        // Config:
+        let entry = r.u32()?;
+        let mtvec = r.u32()?;
+        let dvec = r.u32()?;
+        let reg_init = r.value_init()?;
+        let fault_mode = match r.u8()? {
+            0 => FaultMode::Error,
+            1 => FaultMode::Trap,
+            x => return Err(anyhow!("Invalid fault mode {x} in snapshot.")),
+        };
+        let zicsr = r.bool()?;
+        let region_count = r.u32()?;
+        let mut mem_regions = vec![];
+        for _ in 0..region_count {
+            let start = r.u32()?;
+            let end = r.u32()?;
+            let init = r.value_init()?;
+            let region_type = match r.u8()? {
+                0 => MemoryRegionType::RAM,
+                1 => MemoryRegionType::ROM,
+                x => return Err(anyhow!("Invalid memory region type {x} in snapshot.")),
+            };
+            mem_regions.push(MemoryRegionConfig {
+                adr_range: start..end,
+                init,
+                region_type,
+            });
+        }
+        let config = DRVSimConfig {
+            entry,
+            mtvec,
+            dvec,
+            mem_regions,
+            reg_init,
+            fault_mode,
+            zicsr,
+        };
+
+        // Memory layout is given by the config:
+        let mut mems = DRVSim::new(config.clone()).mems;
+
+        // Core state:
+        let pc = r.u32()?;
+        let flags = r.u8()?;
+        let irq_pending = r.u32()?;
+        let device_irq = r.u32()?;
+        let irq_enable = r.u32()?;
+
+        // CSRs:
+        let mut csr = CsrFile::new(mtvec);
+        csr.mtvec = r.u32()?;
+        csr.mscratch = r.u32()?;
+        csr.mcause = r.u32()?;
+        csr.mtval = r.u32()?;
+        csr.mpie = r.bool()?;
+        csr.mcycle = r.u64()?;
+        csr.minstret = r.u64()?;
+
+        // Registers:
+        let mut core_reg = RegisterFile::default();
+        for _ in 0..r.u32()? {
+            let reg = Register::new(r.u8()? as u32)?;
+            core_reg.set(reg, r.u32()?);
+        }
+
+        // Memories:
+        for region in mems.iter_mut() {
+            for _ in 0..r.u32()? {
+                let block_idx = r.u32()?;
+                let bitmap = r.bytes((BLOCK_SIZE / 8) as usize)?.to_vec();
+                let data = r.bytes(BLOCK_SIZE as usize)?;
+                let mut block = [None; BLOCK_SIZE as usize];
+                for (idx, val) in block.iter_mut().enumerate() {
+                    if bitmap[idx / 8] & (1 << (idx % 8)) != 0 {
+                        *val = Some(data[idx]);
+                    }
+                }
+                if let RegionBacking::Memory(mem) = &mut region.backing {
+                    mem.insert_block(block_idx, block);
+                }
+            }
+        }
+
+        // Symbols:
+        let mut symbols = SymbolTable::new();
+        for _ in 0..r.u32()? {
+            let adr = r.u32()?;
+            let size = r.u32()?;
+            let len = r.u32()? as usize;
+            let name = std::str::from_utf8(r.bytes(len)?)
+                .map_err(|_| anyhow!("Invalid symbol name in snapshot."))?;
+            symbols.insert(Symbol {
+                name: name.to_owned(),
+                adr,
+                size,
+            });
+        }
+
+        if r.pos != data.len() {
+            return Err(anyhow!("Unexpected trailing data in snapshot."));
+        }
+
+        Ok(Snapshot {
+            core_reg,
+            pc,
+            handling_trap: flags & 0b0001 != 0,
+            debug_mode: flags & 0b0010 != 0,
+            dbg_req: flags & 0b0100 != 0,
+            irq_pending,
+            device_irq,
+            irq_enable,
+            irq_global_enable: flags & 0b1000 != 0,
+            csr,
+            mems,
+            symbols,
+            config,
+        })
+    }
+}
+
+struct SnapshotWriter {
+    data: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    fn u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    fn bool(&mut self, val: bool) {
+        self.data.push(val as u8);
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    fn value_init(&mut self, init: ValueInit) {
+        let (tag, arg) = match init {
+            ValueInit::Random => (0, 0),
+            ValueInit::Zero => (1, 0),
+            ValueInit::Ones => (2, 0),
+            ValueInit::Error => (3, 0),
+            ValueInit::FixedByte(b) => (4, b as u32),
+            ValueInit::FixedWord(w) => (5, w),
+        };
+        self.u8(tag);
+        self.u32(arg);
+    }
+}
+
+struct SnapshotReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], anyhow::Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(anyhow!("Snapshot is truncated."))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, anyhow::Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u32(&mut self) -> Result<u32, anyhow::Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, anyhow::Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    fn value_init(&mut self) -> Result<ValueInit, anyhow::Error> {
+        let tag = self.u8()?;
+        let arg = self.u32()?;
+        match tag {
+            0 => Ok(ValueInit::Random),
+            1 => Ok(ValueInit::Zero),
+            2 => Ok(ValueInit::Ones),
+            3 => Ok(ValueInit::Error),
+            4 => Ok(ValueInit::FixedByte(arg as u8)),
+            5 => Ok(ValueInit::FixedWord(arg)),
+            _ => Err(anyhow!("Invalid value init {tag} in snapshot.")),
+        }
+    }
+}
+
+// ==== Snapshot Tests =============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{inst::Register, run::HaltReason, snapshot::*, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+
+    fn new_simulator(insts: Vec<u32>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: ROM_START + 0x4000,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Trap,
+            zicsr: true,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    // Stores x1+1, x1+2, ... to RAM_START, RAM_START+4, ..., with x2 = RAM_START:
+    fn store_loop() -> Vec<u32> {
+        vec![
+            0x02000137, // LUI x2, 0x2000
+            0x00000093, // ADDI x1, x0, 0
+            0x00108093, // ADDI x1, x1, 1
+            0x00112023, // SW x1, 0x0(x2)
+            0x00410113, // ADDI x2, x2, 4
+            0xff5ff06f, // JAL x0, .-12
+        ]
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut sim = new_simulator(store_loop());
+        assert!(matches!(sim.run(11), HaltReason::StepLimit));
+        let snapshot = sim.snapshot();
+
+        assert!(matches!(sim.run(100), HaltReason::StepLimit));
+        assert_ne!(sim.read_register(Register::X1).unwrap().val, 3);
+
+        sim.restore(&snapshot);
+        assert_eq!(sim.pc, ROM_START + 0xC);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 3);
+        assert_eq!(sim.read_w(RAM_START + 4).unwrap().val, 2);
+        assert!(sim.read_w(RAM_START + 8).is_err());
+        assert_eq!(sim.cycle(), 11);
+    }
+
+    #[test]
+    fn snapshot_serialization() {
+        let mut sim = new_simulator(store_loop());
+        assert!(matches!(sim.run(10), HaltReason::StepLimit));
+        sim.write_b(RAM_START + 0x1000, 0xAB).unwrap();
+        sim.raise_irq(3);
+        sim.set_irq_enable(0x8);
+        sim.write_csr(csr::MSCRATCH, 0x1234).unwrap();
+        for (name, adr) in [("main", ROM_START), ("loop", ROM_START + 8)] {
+            sim.symbols_mut().insert(Symbol {
+                name: name.to_owned(),
+                adr,
+                size: 0,
+            });
+        }
+        let snapshot = sim.snapshot();
+
+        let data = snapshot.to_bytes().unwrap();
+        let mut restored = DRVSim::from_snapshot(&Snapshot::from_bytes(&data).unwrap());
+        assert_eq!(restored.snapshot().to_bytes().unwrap(), data);
+
+        assert_eq!(restored.pc, sim.pc);
+        assert_eq!(
+            restored.read_register(Register::X2).unwrap().val,
+            RAM_START + 8
+        );
+        assert_eq!(restored.read_b(RAM_START + 0x1000).unwrap().val, 0xAB);
+        assert_eq!(restored.irq_pending(), 0x8);
+        assert_eq!(restored.irq_enable(), 0x8);
+        assert_eq!(restored.read_csr(csr::MSCRATCH).unwrap().val, 0x1234);
+        assert_eq!(restored.cycle(), 10);
+        assert_eq!(restored.config.fault_mode, FaultMode::Trap);
+        assert!(restored.config.zicsr);
+        assert_eq!(
+            restored.symbols().format_adr(ROM_START + 0xC),
+            "0x0100000c <loop+0x4>"
+        );
+
+        // Uninitialized registers and bytes within an allocated block stay uninitialized:
+        assert!(restored.read_register(Register::X3).is_err());
+        assert!(restored.read_b(RAM_START + 0x1001).is_err());
+        assert!(restored.read_b(RAM_START + 0x2000).is_err());
+
+        // Both simulators continue identically:
+        sim.run(20);
+        restored.run(20);
+        assert_eq!(
+            restored.snapshot().to_bytes().unwrap(),
+            sim.snapshot().to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn snapshot_file() {
+        let mut sim = new_simulator(store_loop());
+        sim.run(10);
+
+        let file = std::env::temp_dir().join(format!("drv_snapshot_{}.bin", std::process::id()));
+        sim.snapshot().save(file.clone()).unwrap();
+        let snapshot = Snapshot::load(file.clone()).unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(
+            snapshot.to_bytes().unwrap(),
+            sim.snapshot().to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn snapshot_invalid() {
+        let data = new_simulator(store_loop()).snapshot().to_bytes().unwrap();
+
+        assert!(Snapshot::from_bytes(&data[..data.len() - 1]).is_err());
+        assert!(Snapshot::from_bytes(&[data.as_slice(), &[0]].concat()).is_err());
+        assert!(Snapshot::from_bytes(b"ELF").is_err());
+
+        let mut wrong_version = data.clone();
+        wrong_version[8] = 2;
+        assert!(Snapshot::from_bytes(&wrong_version).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::DRVSim;
+
+// ==== Type Definitions ===========================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub adr: u32,
+    pub size: u32, // Zero if unknown, such as for assembly labels.
+}
+
+// Symbols, sorted by address.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+// ==== SymbolTable Implementation =================================================================
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    // Add a symbol. Symbols sharing an address are looked up in the order they were added.
+    pub fn insert(&mut self, symbol: Symbol) {
+        let idx = self.symbols.partition_point(|s| s.adr <= symbol.adr);
+        self.symbols.insert(idx, symbol);
+    }
+
+    // Add all symbols of another table, replacing any existing symbols of the same names, such as
+    // when a program is reloaded.
+    pub fn merge(&mut self, other: SymbolTable) {
+        self.symbols.retain(|s| other.by_name(&s.name).is_none());
+        for symbol in other.symbols {
+            self.insert(symbol);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    // Symbol containing an address, and the offset of the address into it. Symbols without a
+    // size extend up to the next symbol.
+    pub fn lookup(&self, adr: u32) -> Option<(&Symbol, u32)> {
+        let end = self.symbols.partition_point(|s| s.adr <= adr);
+        let closest = self.symbols[..end].last()?.adr;
+        let first = self.symbols[..end].partition_point(|s| s.adr < closest);
+        let symbol = &self.symbols[first];
+        let offset = adr - symbol.adr;
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        Some((symbol, offset))
+    }
+
+    // Format an address as '0x01000010 <memcpy+0x14>', or '0x01000010' if no symbol contains it.
+    pub fn format_adr(&self, adr: u32) -> String {
+        match self.lookup(adr) {
+            Some((symbol, 0)) => format!("0x{:08x} <{}>", adr, symbol.name),
+            Some((symbol, offset)) => format!("0x{:08x} <{}+0x{:x}>", adr, symbol.name, offset),
+            None => format!("0x{:08x}", adr),
+        }
+    }
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Symbols loaded from ELF files.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
+}
+
+// ==== Symbol Tests ===============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::symbols::*;
+
+    fn symbol(name: &str, adr: u32, size: u32) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            adr,
+            size,
+        }
+    }
+
+    #[test]
+    fn symbol_lookup() {
+        let mut symbols = SymbolTable::new();
+        assert!(symbols.lookup(0x100).is_none());
+
+        symbols.insert(symbol("memcpy", 0x200, 0x20));
+        symbols.insert(symbol("main", 0x100, 0));
+        symbols.insert(symbol("_start", 0x100, 0));
+
+        assert_eq!(
+            symbols.lookup(0x100).unwrap(),
+            (&symbol("main", 0x100, 0), 0)
+        );
+        assert_eq!(symbols.lookup(0x1F0).unwrap().1, 0xF0);
+        assert_eq!(symbols.lookup(0x214).unwrap().0.name, "memcpy");
+        assert!(symbols.lookup(0xFF).is_none());
+        assert!(symbols.lookup(0x220).is_none());
+
+        assert_eq!(symbols.format_adr(0x100), "0x00000100 <main>");
+        assert_eq!(symbols.format_adr(0x214), "0x00000214 <memcpy+0x14>");
+        assert_eq!(symbols.format_adr(0x220), "0x00000220");
+
+        assert_eq!(symbols.by_name("memcpy").unwrap().adr, 0x200);
+        assert!(symbols.by_name("memset").is_none());
+    }
+
+    #[test]
+    fn symbol_merge() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(symbol("main", 0x100, 0));
+        symbols.insert(symbol("memcpy", 0x200, 0x20));
+
+        let mut reloaded = SymbolTable::new();
+        reloaded.insert(symbol("main", 0x180, 0));
+        reloaded.insert(symbol("memset", 0x300, 0x10));
+        symbols.merge(reloaded);
+
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["main", "memcpy", "memset"]);
+        assert_eq!(symbols.by_name("main").unwrap().adr, 0x180);
+    }
+}
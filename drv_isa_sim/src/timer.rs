@@ -0,0 +1,227 @@
+use anyhow::anyhow;
+
+use crate::device::Device;
+
+// ==== Register Offsets ===========================================================================
+
+pub const TIMER_MTIME: u32 = 0x0;
+pub const TIMER_MTIMEH: u32 = 0x4;
+pub const TIMER_MTIMECMP: u32 = 0x8;
+pub const TIMER_MTIMECMPH: u32 = 0xC;
+
+// Machine timer interrupt, as numbered by the privileged spec:
+pub const TIMER_IRQ_LINE: u32 = 7;
+
+// ==== Type Definitions ===========================================================================
+
+// Machine timer. mtime counts simulated cycles, and the interrupt line is asserted while
+// mtime >= mtimecmp. Both are 64 bit registers, accessed as two 32 bit words.
+pub struct Timer {
+    mtime: u64,
+    mtimecmp: u64,
+    irq_line: u32,
+}
+
+// ==== Timer Implementation =======================================================================
+
+impl Timer {
+    pub fn new(irq_line: u32) -> Timer {
+        assert!(irq_line < 32);
+        Timer {
+            mtime: 0,
+            mtimecmp: u64::MAX,
+            irq_line,
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn set_mtime(&mut self, mtime: u64) {
+        self.mtime = mtime;
+    }
+
+    pub fn mtimecmp(&self) -> u64 {
+        self.mtimecmp
+    }
+
+    pub fn set_mtimecmp(&mut self, mtimecmp: u64) {
+        self.mtimecmp = mtimecmp;
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new(TIMER_IRQ_LINE)
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u32, bytes: u32) -> Result<u32, anyhow::Error> {
+        if bytes != 4 {
+            return Err(anyhow!("Timer registers only support word accesses."));
+        }
+        match offset {
+            TIMER_MTIME => Ok(self.mtime as u32),
+            TIMER_MTIMEH => Ok((self.mtime >> 32) as u32),
+            TIMER_MTIMECMP => Ok(self.mtimecmp as u32),
+            TIMER_MTIMECMPH => Ok((self.mtimecmp >> 32) as u32),
+            _ => Err(anyhow!(
+                "Attempted to read invalid timer register 0x{offset:x}."
+            )),
+        }
+    }
+
+    fn write(&mut self, offset: u32, bytes: u32, val: u32) -> Result<(), anyhow::Error> {
+        if bytes != 4 {
+            return Err(anyhow!("Timer registers only support word accesses."));
+        }
+        let val = val as u64;
+        match offset {
+            TIMER_MTIME => self.mtime = (self.mtime & !0xFFFFFFFF) | val,
+            TIMER_MTIMEH => self.mtime = (self.mtime & 0xFFFFFFFF) | (val << 32),
+            TIMER_MTIMECMP => self.mtimecmp = (self.mtimecmp & !0xFFFFFFFF) | val,
+            TIMER_MTIMECMPH => self.mtimecmp = (self.mtimecmp & 0xFFFFFFFF) | (val << 32),
+            _ => {
+                return Err(anyhow!(
+                    "Attempted to write invalid timer register 0x{offset:x}."
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.mtime = u64::wrapping_add(self.mtime, 1);
+    }
+
+    fn irq_lines(&self) -> u32 {
+        if self.mtime >= self.mtimecmp {
+            1 << self.irq_line
+        } else {
+            0
+        }
+    }
+}
+
+// ==== Timer Tests ================================================================================
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        inst::Register,
+        run::HaltReason,
+        timer::*,
+        trap::{Trap, TrapCause},
+        *,
+    };
+
+    const ROM_START: u32 = 0x1000000;
+    const MTVEC: u32 = ROM_START + 0x4000;
+    const TIMER_START: u32 = 0x3000000;
+
+    fn new_simulator(insts: Vec<u32>, handler: Vec<u32>, timer: Rc<RefCell<Timer>>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: MTVEC,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: TIMER_START..TIMER_START + 0x10,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::Device(timer),
+                },
+            ],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        for (idx, inst) in handler.iter().enumerate() {
+            sim.program_w(MTVEC + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    #[test]
+    fn timer_registers() {
+        let mut timer = Timer::default();
+        assert_eq!(timer.irq_lines(), 0);
+
+        timer.write(TIMER_MTIMEH, 4, 0x1).unwrap();
+        timer.write(TIMER_MTIME, 4, 0xFFFFFFFF).unwrap();
+        assert_eq!(timer.mtime(), 0x1FFFFFFFF);
+        timer.tick();
+        assert_eq!(timer.read(TIMER_MTIME, 4).unwrap(), 0);
+        assert_eq!(timer.read(TIMER_MTIMEH, 4).unwrap(), 2);
+
+        timer.write(TIMER_MTIMECMPH, 4, 0x2).unwrap();
+        timer.write(TIMER_MTIMECMP, 4, 0x1).unwrap();
+        assert_eq!(timer.mtimecmp(), 0x200000001);
+        assert_eq!(timer.irq_lines(), 0);
+        timer.tick();
+        assert_eq!(timer.irq_lines(), 1 << TIMER_IRQ_LINE);
+
+        assert!(timer.read(TIMER_MTIME, 1).is_err());
+        assert!(timer.read(0x10, 4).is_err());
+        assert!(timer.write(0x10, 4, 0).is_err());
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let timer = Rc::new(RefCell::new(Timer::default()));
+        let mut sim = new_simulator(
+            vec![
+                0x030000b7, // LUI x1, 0x3000
+                0x01400113, // ADDI x2, x0, 20
+                0x0000a623, // SW x0, 0xC(x1)
+                0x0020a423, // SW x2, 0x8(x1)
+                0x00118193, // ADDI x3, x3, 1
+                0xffdff06f, // JAL x0, .-4
+            ],
+            vec![
+                0x00120213, // ADDI x4, x4, 1
+                0xfff00293, // ADDI x5, x0, -1
+                0x0050a623, // SW x5, 0xC(x1)
+                0x30200073, // MRET
+            ],
+            timer.clone(),
+        );
+        sim.set_irq_enable(1 << TIMER_IRQ_LINE);
+        sim.set_global_irq_enable(true);
+
+        let mut trap = None;
+        let reason = sim.run_until(100, |log| {
+            trap = log.trap;
+            log.trap.is_some()
+        });
+        assert!(matches!(reason, HaltReason::Predicate));
+        assert_eq!(
+            trap,
+            Some(Trap {
+                cause: TrapCause::Interrupt(TIMER_IRQ_LINE),
+                tval: 0
+            })
+        );
+        // Interrupt is taken in the step after mtime reached mtimecmp:
+        assert_eq!(sim.cycle(), 21);
+        assert_eq!(timer.borrow().mtime(), 21);
+        assert_eq!(sim.irq_pending(), 1 << TIMER_IRQ_LINE);
+
+        // Handler pushes mtimecmp out of reach, and returns:
+        assert!(matches!(sim.run(100), HaltReason::StepLimit));
+        assert_eq!(sim.read_register(Register::X4).unwrap().val, 1);
+        assert_eq!(sim.irq_pending(), 0);
+        assert!(!sim.handling_trap());
+    }
+}
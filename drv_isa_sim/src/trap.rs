@@ -0,0 +1,157 @@
+use crate::DRVSim;
+
+// ==== Type Definitions ===========================================================================
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Exception {
+    InstructionAddressMisaligned,
+    InstructionAccessFault,
+    IllegalInstruction,
+    Breakpoint,
+    LoadAddressMisaligned,
+    LoadAccessFault,
+    StoreAddressMisaligned,
+    StoreAccessFault,
+    EnvironmentCall,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TrapCause {
+    Exception(Exception), // Synchronous exception raised by an instruction.
+    Interrupt(u32),       // Asynchronous interrupt on the given interrupt line.
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Trap {
+    pub cause: TrapCause,
+    pub tval: u32, // Exception-specific trap value (faulting address or instruction).
+}
+
+// ==== Exception Implementation ===================================================================
+
+impl Exception {
+    // Exception code, as reported in mcause:
+    pub fn code(&self) -> u32 {
+        match self {
+            Exception::InstructionAddressMisaligned => 0,
+            Exception::InstructionAccessFault => 1,
+            Exception::IllegalInstruction => 2,
+            Exception::Breakpoint => 3,
+            Exception::LoadAddressMisaligned => 4,
+            Exception::LoadAccessFault => 5,
+            Exception::StoreAddressMisaligned => 6,
+            Exception::StoreAccessFault => 7,
+            Exception::EnvironmentCall => 11,
+        }
+    }
+}
+
+impl std::fmt::Display for Exception {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exception::InstructionAddressMisaligned => write!(f, "instruction address misaligned"),
+            Exception::InstructionAccessFault => write!(f, "instruction access fault"),
+            Exception::IllegalInstruction => write!(f, "illegal instruction"),
+            Exception::Breakpoint => write!(f, "breakpoint"),
+            Exception::LoadAddressMisaligned => write!(f, "load address misaligned"),
+            Exception::LoadAccessFault => write!(f, "load access fault"),
+            Exception::StoreAddressMisaligned => write!(f, "store address misaligned"),
+            Exception::StoreAccessFault => write!(f, "store access fault"),
+            Exception::EnvironmentCall => write!(f, "environment call"),
+        }
+    }
+}
+
+// ==== TrapCause Implementation ===================================================================
+
+impl TrapCause {
+    // Value reported in mcause: The MSB indicates an interrupt, the remaining bits contain the
+    // exception code or interrupt line.
+    pub fn code(&self) -> u32 {
+        match self {
+            TrapCause::Exception(exception) => exception.code(),
+            TrapCause::Interrupt(line) => 0x80000000 | line,
+        }
+    }
+
+    pub fn is_interrupt(&self) -> bool {
+        matches!(self, TrapCause::Interrupt(_))
+    }
+}
+
+impl std::fmt::Display for TrapCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrapCause::Exception(exception) => write!(f, "{exception}"),
+            TrapCause::Interrupt(line) => write!(f, "interrupt line {line}"),
+        }
+    }
+}
+
+// ==== Trap Implementation ========================================================================
+
+impl std::fmt::Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (cause 0x{:08x}, tval 0x{:08x})",
+            self.cause,
+            self.cause.code(),
+            self.tval
+        )
+    }
+}
+
+// ==== Interrupt Implementation ===================================================================
+
+impl DRVSim {
+    // Assert interrupt line 'line'. Lines are level-sensitive, and remain pending until lowered.
+    pub fn raise_irq(&mut self, line: u32) {
+        assert!(line < 32);
+        self.irq_pending |= 1 << line;
+    }
+
+    // De-assert interrupt line 'line'.
+    pub fn lower_irq(&mut self, line: u32) {
+        assert!(line < 32);
+        self.irq_pending &= !(1 << line);
+    }
+
+    // Level of each interrupt line, including lines driven by devices.
+    pub fn irq_pending(&self) -> u32 {
+        self.irq_pending | self.device_irq
+    }
+
+    // Set the mask of interrupt lines that may interrupt the core.
+    pub fn set_irq_enable(&mut self, mask: u32) {
+        self.irq_enable = mask;
+    }
+
+    pub fn irq_enable(&self) -> u32 {
+        self.irq_enable
+    }
+
+    // Enable or disable all interrupts.
+    pub fn set_global_irq_enable(&mut self, enable: bool) {
+        self.irq_global_enable = enable;
+    }
+
+    pub fn global_irq_enable(&self) -> bool {
+        self.irq_global_enable
+    }
+
+    // Interrupt line to be taken at the next instruction boundary, if any. Interrupts are
+    // not taken while handling a trap or in debug mode. The lowest pending line wins.
+    pub(crate) fn pending_interrupt(&self) -> Option<u32> {
+        if !self.irq_global_enable || self.handling_trap || self.debug_mode {
+            return None;
+        }
+
+        let active = self.irq_pending() & self.irq_enable;
+        if active == 0 {
+            None
+        } else {
+            Some(active.trailing_zeros())
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::VecDeque,
+    io::{stdout, Write},
+};
+
+use anyhow::anyhow;
+
+use crate::device::Device;
+
+// ==== Register Offsets ===========================================================================
+
+pub const UART_TX: u32 = 0x0; // Write: Transmit the lowest byte. Reads as zero.
+pub const UART_RX: u32 = 0x4; // Read: Pop the next received byte, zero if none is available.
+pub const UART_STATUS: u32 = 0x8; // Read: RX valid (bit 0), TX ready (bit 1).
+
+pub const UART_STATUS_RX_VALID: u32 = 1 << 0;
+pub const UART_STATUS_TX_READY: u32 = 1 << 1;
+
+// ==== Type Definitions ===========================================================================
+
+// UART with host-visible buffers. Transmitted bytes are captured, and optionally echoed to
+// stdout. Received bytes are supplied by the host.
+#[derive(Default)]
+pub struct Uart {
+    output: Vec<u8>,
+    input: VecDeque<u8>,
+    echo: bool, // Stream transmitted bytes to stdout.
+}
+
+// ==== Uart Implementation ========================================================================
+
+impl Uart {
+    pub fn new() -> Uart {
+        Uart::default()
+    }
+
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    // Transmitted bytes, as text:
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    pub fn output_bytes(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    // Queue bytes to be received by the firmware:
+    pub fn push_input(&mut self, data: &[u8]) {
+        self.input.extend(data);
+    }
+
+    // Number of queued bytes not yet read by the firmware.
+    pub fn input_pending(&self) -> usize {
+        self.input.len()
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: u32, _bytes: u32) -> Result<u32, anyhow::Error> {
+        match offset {
+            UART_TX => Ok(0),
+            UART_RX => Ok(self.input.pop_front().unwrap_or(0) as u32),
+            UART_STATUS => {
+                let mut status = UART_STATUS_TX_READY;
+                if !self.input.is_empty() {
+                    status |= UART_STATUS_RX_VALID;
+                }
+                Ok(status)
+            }
+            _ => Err(anyhow!(
+                "Attempted to read invalid UART register 0x{offset:x}."
+            )),
+        }
+    }
+
+    fn write(&mut self, offset: u32, _bytes: u32, val: u32) -> Result<(), anyhow::Error> {
+        match offset {
+            UART_TX => {
+                let byte = (val & 0xFF) as u8;
+                self.output.push(byte);
+                if self.echo {
+                    let mut stdout = stdout().lock();
+                    stdout.write_all(&[byte])?;
+                    stdout.flush()?;
+                }
+                Ok(())
+            }
+            UART_RX | UART_STATUS => Ok(()), // Read-only.
+            _ => Err(anyhow!(
+                "Attempted to write invalid UART register 0x{offset:x}."
+            )),
+        }
+    }
+}
+
+// ==== UART Tests =================================================================================
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{inst::Register, uart::*, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const UART_START: u32 = 0x3000000;
+
+    fn new_simulator(insts: Vec<u32>, uart: Rc<RefCell<Uart>>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: 0,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: UART_START..UART_START + 0x10,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::Device(uart),
+                },
+            ],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    #[test]
+    fn uart_tx() {
+        let uart = Rc::new(RefCell::new(Uart::new()));
+        let mut sim = new_simulator(
+            vec![
+                0x030000b7, // LUI x1, 0x3000
+                0x06800113, // ADDI x2, x0, 'h'
+                0x00208023, // SB x2, 0x0(x1)
+                0x06500113, // ADDI x2, x0, 'e'
+                0x00208023, // SB x2, 0x0(x1)
+                0x06c00113, // ADDI x2, x0, 'l'
+                0x00208023, // SB x2, 0x0(x1)
+                0x00208023, // SB x2, 0x0(x1)
+                0x06f00113, // ADDI x2, x0, 'o'
+                0x0020a023, // SW x2, 0x0(x1)
+                0x0000006f, // JAL x0, .+0
+            ],
+            uart.clone(),
+        );
+
+        sim.run(100);
+        assert_eq!(uart.borrow().output(), "hello");
+        assert_eq!(uart.borrow().output_bytes(), b"hello");
+
+        uart.borrow_mut().clear_output();
+        assert_eq!(uart.borrow().output(), "");
+    }
+
+    #[test]
+    fn uart_rx() {
+        let uart = Rc::new(RefCell::new(Uart::new()));
+        let mut sim = new_simulator(
+            vec![
+                0x030000b7, // LUI x1, 0x3000
+                0x0080a103, // LW x2, 0x8(x1)
+                0x0040c183, // LBU x3, 0x4(x1)
+                0x0080a203, // LW x4, 0x8(x1)
+                0x0040c283, // LBU x5, 0x4(x1)
+                0x0080a303, // LW x6, 0x8(x1)
+                0x0040c383, // LBU x7, 0x4(x1)
+            ],
+            uart.clone(),
+        );
+
+        uart.borrow_mut().push_input(b"ok");
+        assert_eq!(uart.borrow().input_pending(), 2);
+        for _ in 0..7 {
+            sim.step().unwrap();
+        }
+
+        let mut reg = |reg| sim.read_register(reg).unwrap().val;
+        assert_eq!(
+            reg(Register::X2),
+            UART_STATUS_RX_VALID | UART_STATUS_TX_READY
+        );
+        assert_eq!(reg(Register::X3), b'o' as u32);
+        assert_eq!(
+            reg(Register::X4),
+            UART_STATUS_RX_VALID | UART_STATUS_TX_READY
+        );
+        assert_eq!(reg(Register::X5), b'k' as u32);
+        assert_eq!(reg(Register::X6), UART_STATUS_TX_READY);
+        assert_eq!(reg(Register::X7), 0);
+        assert_eq!(uart.borrow().input_pending(), 0);
+    }
+
+    #[test]
+    fn uart_invalid_register() {
+        let mut uart = Uart::new();
+        assert!(uart.read(0xC, 4).is_err());
+        assert!(uart.write(0xC, 4, 0).is_err());
+        assert!(uart.write(UART_STATUS, 4, 0).is_ok());
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::VecDeque;
+
+use anyhow::anyhow;
+
+use crate::{
+    breakpoint::{BreakpointHit, MemAccess},
+    csr::CsrFile,
+    inst::Register,
+    run::HaltReason,
+    DRVSim, RegionBacking,
+};
+
+// ==== Type Definitions ===========================================================================
+
+// Bounded journal of the state overwritten by each step, used to step backwards.
+#[derive(Default)]
+pub(crate) struct UndoJournal {
+    depth: usize,              // Maximum number of steps that can be undone.
+    steps: VecDeque<UndoStep>, // Undoable steps, oldest first.
+    current: Option<UndoStep>, // Step being recorded.
+}
+
+// State before a step. Register and memory writes, including the initialization of uninitialized
+// registers and memory on first read, are recorded in the order they occured.
+struct UndoStep {
+    pc: u32,
+    handling_trap: bool,
+    debug_mode: bool,
+    irq_enable: u32,
+    irq_global_enable: bool,
+    csr: CsrFile,
+    regs: Vec<(Register, Option<u32>)>, // Previous register values. None if uninitialized.
+    mems: Vec<MemUndo>,
+}
+
+struct MemUndo {
+    adr: u32,
+    bytes: u32,
+    val: Option<u32>, // Value written. None if the bytes were initialized by a read.
+    prev: [Option<u8>; 4], // Previous bytes. None if uninitialized.
+}
+
+// ==== UndoJournal Implementation =================================================================
+
+impl UndoJournal {
+    // Drop all recorded steps, keeping the depth:
+    pub(crate) fn clear(&mut self) {
+        self.steps.clear();
+        self.current = None;
+    }
+}
+
+// ==== DRVSim Implementation ======================================================================
+
+impl DRVSim {
+    // Set the number of steps that can be undone with step_back. Recording is disabled with a
+    // depth of zero, which is the default.
+    pub fn set_undo_depth(&mut self, depth: usize) {
+        self.undo.depth = depth;
+        while self.undo.steps.len() > depth {
+            self.undo.steps.pop_front();
+        }
+    }
+
+    // Number of steps that can currently be undone.
+    pub fn undo_available(&self) -> usize {
+        self.undo.steps.len()
+    }
+
+    // Undo the most recent step. Interrupt lines, the debug request input and memory-mapped
+    // side effects are not reverted.
+    pub fn step_back(&mut self) -> Result<(), anyhow::Error> {
+        let step = self
+            .undo
+            .steps
+            .pop_back()
+            .ok_or(anyhow!("No steps to undo."))?;
+
+        for (reg, prev) in step.regs.into_iter().rev() {
+            match prev {
+                Some(val) => {
+                    self.core_reg.set(reg, val);
+                }
+                None => {
+                    self.core_reg.clear(reg);
+                }
+            }
+        }
+
+        for mem in step.mems.iter().rev() {
+            // Writes were only recorded once the region was known to contain the address:
+            let region_idx = self.find_mem_region(mem.adr, mem.bytes)?;
+            if let RegionBacking::Memory(region_mem) = &mut self.mems[region_idx].backing {
+                for offset in 0..mem.bytes {
+                    region_mem.restore_b(mem.adr + offset, mem.prev[offset as usize]);
+                }
+            }
+        }
+
+        self.pc = step.pc;
+        self.breakpoints.resume_pc = None;
+        self.handling_trap = step.handling_trap;
+        self.debug_mode = step.debug_mode;
+        self.irq_enable = step.irq_enable;
+        self.irq_global_enable = step.irq_global_enable;
+        self.csr = step.csr;
+
+        // Report write watchpoints triggered by the undone step:
+        self.breakpoints.hit = None;
+        for mem in step.mems {
+            if let Some(val) = mem.val {
+                self.check_watchpoints(MemAccess {
+                    adr: mem.adr,
+                    bytes: mem.bytes,
+                    val,
+                    write: true,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Step backwards until a breakpoint is reached, the undone instruction triggers a write
+    // watchpoint, or the journal is exhausted, for at most max_steps instructions.
+    pub fn reverse_continue(&mut self, max_steps: u64) -> HaltReason {
+        for _ in 0..max_steps {
+            if self.undo.steps.is_empty() {
+                return HaltReason::StartOfHistory;
+            }
+
+            if let Err(err) = self.step_back() {
+                return HaltReason::Error(err);
+            }
+
+            if let Some(hit) = self.breakpoints.hit.take() {
+                return HaltReason::Breakpoint(hit);
+            }
+
+            if let Some(id) = self.breakpoints.pc_breakpoint(self.pc) {
+                return HaltReason::Breakpoint(BreakpointHit {
+                    id,
+                    pc: self.pc,
+                    access: None,
+                });
+            }
+        }
+
+        HaltReason::StepLimit
+    }
+
+    // Start recording the state overwritten by a step:
+    pub(crate) fn begin_undo_step(&mut self) {
+        if self.undo.depth == 0 {
+            return;
+        }
+
+        self.undo.current = Some(UndoStep {
+            pc: self.pc,
+            handling_trap: self.handling_trap,
+            debug_mode: self.debug_mode,
+            irq_enable: self.irq_enable,
+            irq_global_enable: self.irq_global_enable,
+            csr: self.csr.clone(),
+            regs: vec![],
+            mems: vec![],
+        });
+    }
+
+    pub(crate) fn end_undo_step(&mut self) {
+        if let Some(step) = self.undo.current.take() {
+            if self.undo.steps.len() == self.undo.depth {
+                self.undo.steps.pop_front();
+            }
+            self.undo.steps.push_back(step);
+        }
+    }
+
+    // Record a register write, returning the previous value:
+    pub(crate) fn journal_reg_write(&mut self, reg: Register) -> Option<u32> {
+        if reg == Register::X0 {
+            return Some(0);
+        }
+
+        let prev = self.core_reg.get(reg);
+        if let Some(step) = self.undo.current.as_mut() {
+            step.regs.push((reg, prev));
+        }
+        prev
+    }
+
+    // Record memory that is about to be initialized by a read, so that it becomes uninitialized
+    // again when the step is undone:
+    pub(crate) fn journal_mem_init(&mut self, region_idx: usize, adr: u32, bytes: u32) {
+        if self.undo.current.is_none() {
+            return;
+        }
+        let Some(prev) = self.mem_prev_bytes(region_idx, adr, bytes) else {
+            return;
+        };
+        if prev[0..bytes as usize].iter().all(|byte| byte.is_some()) {
+            return;
+        }
+        if let Some(step) = self.undo.current.as_mut() {
+            step.mems.push(MemUndo {
+                adr,
+                bytes,
+                val: None,
+                prev,
+            });
+        }
+    }
+
+    // Bytes about to be overwritten or initialized by a memory access. None for devices, whose
+    // accesses are not recorded.
+    pub(crate) fn mem_prev_bytes(
+        &self,
+        region_idx: usize,
+        adr: u32,
+        bytes: u32,
+    ) -> Option<[Option<u8>; 4]> {
+        let RegionBacking::Memory(mem) = &self.mems[region_idx].backing else {
+            return None;
+        };
+
+        let mut prev = [None; 4];
+        for offset in 0..bytes {
+            prev[offset as usize] = mem.peek_b(adr + offset);
+        }
+        Some(prev)
+    }
+
+    // Record a completed memory write, returning the previous value if it was fully initialized.
+    pub(crate) fn journal_mem_write(
+        &mut self,
+        adr: u32,
+        bytes: u32,
+        val: u32,
+        prev: [Option<u8>; 4],
+    ) -> Option<u32> {
+        if let Some(step) = self.undo.current.as_mut() {
+            step.mems.push(MemUndo {
+                adr,
+                bytes,
+                val: Some(val),
+                prev,
+            });
+        }
+
+        prev[0..bytes as usize]
+            .iter()
+            .rev()
+            .try_fold(0, |acc, byte| byte.map(|byte| (acc << 8) | byte as u32))
+    }
+}
+
+// ==== Undo Tests =================================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::{breakpoint::WatchKind, inst::Register, run::HaltReason, *};
+
+    const ROM_START: u32 = 0x1000000;
+    const RAM_START: u32 = 0x2000000;
+    const MTVEC: u32 = ROM_START + 0x4000;
+
+    fn new_simulator(insts: Vec<u32>) -> DRVSim {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: MTVEC,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Error,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim
+    }
+
+    // Stores x1+1, x1+2, ... to RAM_START, RAM_START+4, ..., with x2 = RAM_START:
+    fn store_loop() -> Vec<u32> {
+        vec![
+            0x02000137, // LUI x2, 0x2000
+            0x00000093, // ADDI x1, x0, 0
+            0x00108093, // ADDI x1, x1, 1
+            0x00112023, // SW x1, 0x0(x2)
+            0x00410113, // ADDI x2, x2, 4
+            0xff5ff06f, // JAL x0, .-12
+        ]
+    }
+
+    #[test]
+    fn commit_prev_values() {
+        let mut sim = new_simulator(store_loop());
+        let log = sim.step().unwrap();
+        assert_eq!(log.commit_values[0].prev, None);
+        for _ in 0..2 {
+            sim.step().unwrap();
+        }
+        let log = sim.step().unwrap(); // SW
+        assert_eq!(log.commit_values[0].prev, None);
+        sim.write_b(RAM_START + 0x4, 0xAB).unwrap();
+        for _ in 0..3 {
+            sim.step().unwrap();
+        }
+        let log = sim.step().unwrap(); // SW
+        assert_eq!(log.commit_values[0].val, 2);
+        assert_eq!(log.commit_values[0].prev, None);
+        let log = sim.step().unwrap(); // ADDI x2
+        assert_eq!(log.commit_values[0].prev, Some(RAM_START + 4));
+        assert_eq!(sim.write_w(RAM_START, 5).unwrap().prev, Some(1));
+    }
+
+    #[test]
+    fn step_back() {
+        let mut sim = new_simulator(store_loop());
+        sim.set_undo_depth(100);
+        assert!(sim.step_back().is_err());
+
+        sim.run(11);
+        let before = sim.snapshot().to_bytes().unwrap();
+        let x1 = sim.read_register(Register::X1).unwrap().val;
+        sim.run(10);
+        assert_eq!(sim.undo_available(), 21);
+
+        for _ in 0..10 {
+            sim.step_back().unwrap();
+        }
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, x1);
+        assert_eq!(sim.snapshot().to_bytes().unwrap(), before);
+
+        // Back to reset, with registers and memory uninitialized again:
+        for _ in 0..11 {
+            sim.step_back().unwrap();
+        }
+        assert_eq!(sim.pc, ROM_START);
+        assert_eq!(sim.cycle(), 0);
+        assert!(sim.read_register(Register::X1).is_err());
+        assert!(sim.read_b(RAM_START).is_err());
+        assert_eq!(sim.peek_register(Register::X1), None);
+        assert_eq!(sim.peek_register(Register::X0), Some(0));
+        assert_eq!(sim.peek_w(RAM_START).unwrap(), None);
+        assert_eq!(sim.peek_w(ROM_START).unwrap(), Some(0x02000137));
+        assert!(sim.peek_w(0).is_err());
+        assert!(sim.step_back().is_err());
+    }
+
+    #[test]
+    fn step_back_trap() {
+        let mut sim = new_simulator(vec![
+            0x00100093, // ADDI x1, x0, 1
+            0x00000073, // ECALL
+        ]);
+        sim.set_undo_depth(10);
+        sim.run(2);
+        assert!(sim.handling_trap());
+        assert_eq!(sim.pc, MTVEC);
+
+        sim.step_back().unwrap();
+        assert!(!sim.handling_trap());
+        assert_eq!(sim.pc, ROM_START + 4);
+        assert!(sim.read_register(Register::Xmpc).is_err());
+    }
+
+    #[test]
+    fn undo_depth() {
+        let mut sim = new_simulator(store_loop());
+        sim.set_undo_depth(5);
+        sim.run(20);
+        assert_eq!(sim.undo_available(), 5);
+        sim.set_undo_depth(2);
+        assert_eq!(sim.undo_available(), 2);
+        sim.step_back().unwrap();
+        sim.step_back().unwrap();
+        assert!(sim.step_back().is_err());
+
+        sim.set_undo_depth(0);
+        sim.run(20);
+        assert_eq!(sim.undo_available(), 0);
+    }
+
+    #[test]
+    fn step_back_init_on_read() {
+        let mut sim = DRVSim::new(DRVSimConfig {
+            entry: ROM_START,
+            mtvec: MTVEC,
+            dvec: 0,
+            mem_regions: vec![
+                MemoryRegionConfig {
+                    adr_range: ROM_START..ROM_START + 0x8000,
+                    init: ValueInit::Error,
+                    region_type: MemoryRegionType::ROM,
+                },
+                MemoryRegionConfig {
+                    adr_range: RAM_START..RAM_START + 0x8000,
+                    init: ValueInit::Zero,
+                    region_type: MemoryRegionType::RAM,
+                },
+            ],
+            reg_init: ValueInit::Zero,
+            fault_mode: FaultMode::Error,
+            zicsr: false,
+        });
+        let insts = [
+            0x02000137, // LUI x2, 0x2000
+            0x00012083, // LW x1, 0x0(x2)
+            0x00018093, // ADDI x1, x3, 0
+        ];
+        for (idx, inst) in insts.iter().enumerate() {
+            sim.program_w(ROM_START + (idx as u32) * 4, *inst).unwrap();
+        }
+        sim.set_undo_depth(10);
+        sim.run(3);
+        assert_eq!(sim.peek_register(Register::X3), Some(0));
+        assert_eq!(sim.peek_w(RAM_START).unwrap(), Some(0));
+
+        // Registers and memory initialized by reads are uninitialized again:
+        for _ in 0..3 {
+            sim.step_back().unwrap();
+        }
+        assert_eq!(sim.peek_register(Register::X3), None);
+        assert_eq!(sim.peek_w(RAM_START).unwrap(), None);
+    }
+
+    #[test]
+    fn step_back_failed_write() {
+        let mut sim = new_simulator(vec![
+            0x01000137, // LUI x2, 0x1000
+            0x00012023, // SW x0, 0x0(x2)
+        ]);
+        sim.set_undo_depth(10);
+        sim.step().unwrap();
+        assert!(sim.step().is_err());
+
+        // The write-protected ROM was not written:
+        sim.add_watchpoint(ROM_START..ROM_START + 4, WatchKind::Write);
+        assert!(matches!(
+            sim.reverse_continue(10),
+            HaltReason::StartOfHistory
+        ));
+        assert_eq!(sim.peek_w(ROM_START).unwrap(), Some(0x01000137));
+    }
+
+    #[test]
+    fn step_back_after_restore() {
+        let mut sim = new_simulator(store_loop());
+        sim.set_undo_depth(100);
+        sim.run(11);
+        let snapshot = sim.snapshot();
+        sim.run(10);
+
+        // Steps recorded against a different state are dropped:
+        sim.restore(&snapshot);
+        assert_eq!(sim.undo_available(), 0);
+        assert!(sim.step_back().is_err());
+
+        sim.run(4);
+        for _ in 0..4 {
+            sim.step_back().unwrap();
+        }
+        assert_eq!(
+            sim.snapshot().to_bytes().unwrap(),
+            snapshot.to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut sim = new_simulator(store_loop());
+        sim.set_undo_depth(1000);
+        sim.run(100);
+
+        // Find the instruction that last wrote RAM_START + 0x8:
+        let id = sim.add_watchpoint(RAM_START + 0x8..RAM_START + 0xC, WatchKind::Write);
+        let HaltReason::Breakpoint(hit) = sim.reverse_continue(1000) else {
+            panic!("Expected breakpoint!");
+        };
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.pc, ROM_START + 0xC);
+        assert_eq!(hit.access.unwrap().val, 3);
+        assert_eq!(sim.read_register(Register::X1).unwrap().val, 3);
+        assert!(sim.read_w(RAM_START + 0x8).is_err());
+        sim.remove_breakpoint(id);
+
+        // Reverse to a breakpoint:
+        let id = sim.add_breakpoint(ROM_START + 0x4);
+        let reason = sim.reverse_continue(1000);
+        assert!(matches!(reason, HaltReason::Breakpoint(hit) if hit.id == id));
+        assert_eq!(sim.pc, ROM_START + 0x4);
+        sim.remove_breakpoint(id);
+
+        assert!(matches!(
+            sim.reverse_continue(1000),
+            HaltReason::StartOfHistory
+        ));
+        assert_eq!(sim.pc, ROM_START);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use drv_isa_sim::{asm::assemble, run::HaltReason, *};
+
+// Note: Must match testdata/sim_config.toml!
+const ROM_START: u32 = 0x1000000;
+const RAM_START: u32 = 0x2000000;
+
+fn drv_sim(elf_file: &str, args: &[&str]) -> Output {
+    drv_sim_with_config("testdata/sim_config.toml", elf_file, args)
+}
+
+fn drv_sim_with_config(config_file: &str, elf_file: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drv-sim"))
+        .args(["--config", config_file, elf_file])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Minimal RV32E ELF file with a single segment:
+fn write_elf(name: &str, adr: u32, data: &[u8]) -> PathBuf {
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0];
+    elf.resize(16, 0);
+    for half in [2u16, 243] {
+        elf.extend(half.to_le_bytes()); // e_type = ET_EXEC, e_machine = EM_RISCV
+    }
+    for word in [1, adr, 52, 0, 0x8] {
+        elf.extend(word.to_le_bytes()); // e_version, e_entry, e_phoff, e_shoff, e_flags = RVE
+    }
+    for half in [52u16, 32, 1, 40, 0, 0] {
+        elf.extend(half.to_le_bytes());
+    }
+    let len = data.len() as u32;
+    for word in [1, 84, adr, adr, len, len, 0x5, 0x4] {
+        elf.extend(word.to_le_bytes());
+    }
+    elf.extend(data);
+
+    let path = std::env::temp_dir().join(format!("drv_sim_{}_{name}.elf", std::process::id()));
+    std::fs::write(&path, elf).unwrap();
+    path
+}
+
+#[test]
+fn drv_sim_trace() {
+    // The program does not set an exit status in a0:
+    let output = drv_sim("testdata/04_call_return.elf", &[]);
+    assert_eq!(output.status.code(), Some(128));
+
+    // The trace matches the library's:
+    let mut sim = DRVSim::new(DRVSimConfig {
+        entry: ROM_START,
+        mtvec: 0,
+        dvec: 0,
+        mem_regions: vec![
+            MemoryRegionConfig {
+                adr_range: ROM_START..ROM_START + 0x8000,
+                init: ValueInit::Error,
+                region_type: MemoryRegionType::ROM,
+            },
+            MemoryRegionConfig {
+                adr_range: RAM_START..RAM_START + 0x8000,
+                init: ValueInit::Error,
+                region_type: MemoryRegionType::RAM,
+            },
+        ],
+        reg_init: ValueInit::Error,
+        fault_mode: FaultMode::Error,
+        zicsr: false,
+    });
+    sim.load_elf("testdata/04_call_return.elf".into()).unwrap();
+    let mut log = String::new();
+    let reason = sim.run_until(1000, |inst| {
+        log.push_str(&inst.to_log_string());
+        log.push('\n');
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), log);
+}
+
+#[test]
+fn drv_sim_exit_status() {
+    let program = assemble("li a0, 42\nloop: j loop", ROM_START).unwrap();
+    let elf = write_elf("exit_status", ROM_START, &program.data);
+    let output = drv_sim(elf.to_str().unwrap(), &["--quiet"]);
+    std::fs::remove_file(elf).unwrap();
+    assert_eq!(output.status.code(), Some(42));
+    assert!(output.stdout.is_empty());
+
+    // Instruction limit:
+    let output = drv_sim("testdata/04_call_return.elf", &["--max-steps", "3"]);
+    assert_eq!(output.status.code(), Some(128));
+    assert_eq!(output.stdout.iter().filter(|b| **b == b'\n').count(), 3);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Instruction limit"));
+
+    // Simulation error, by executing uninitialized ROM:
+    let program = assemble("j .+0x10", ROM_START).unwrap();
+    let elf = write_elf("error", ROM_START, &program.data);
+    let output = drv_sim(elf.to_str().unwrap(), &[]);
+    std::fs::remove_file(elf).unwrap();
+    assert_eq!(output.status.code(), Some(128));
+
+    // Exit status that is uninitialized or out of range:
+    for (name, text, msg) in [
+        ("uninit", "loop: j loop", "uninitialized"),
+        ("range", "li a0, 128\nebreak", "exceeds 127"),
+    ] {
+        let program = assemble(text, ROM_START).unwrap();
+        let elf = write_elf(name, ROM_START, &program.data);
+        let output = drv_sim(elf.to_str().unwrap(), &["--quiet"]);
+        std::fs::remove_file(elf).unwrap();
+        assert_eq!(output.status.code(), Some(128), "{text}");
+        assert!(
+            String::from_utf8(output.stderr).unwrap().contains(msg),
+            "{text}"
+        );
+    }
+}
+
+#[test]
+fn drv_sim_uart() {
+    let config = std::env::temp_dir().join(format!("drv_sim_{}_uart.toml", std::process::id()));
+    let config_text = std::fs::read_to_string("testdata/sim_config.toml").unwrap();
+    let config_text =
+        config_text + "\n[[mem_regions]]\nstart = 0x3000000\nsize = 0x10\ntype = \"uart\"\n";
+    std::fs::write(&config, config_text).unwrap();
+
+    let program = assemble(
+        "li a1, 0x3000000\nli a0, 104\nsw a0, 0(a1)\nli a0, 105\nsw a0, 0(a1)\nli a0, 0\nebreak",
+        ROM_START,
+    )
+    .unwrap();
+    let elf = write_elf("uart", ROM_START, &program.data);
+    let output = drv_sim_with_config(config.to_str().unwrap(), elf.to_str().unwrap(), &[]);
+    std::fs::remove_file(elf).unwrap();
+    std::fs::remove_file(config).unwrap();
+
+    // UART output goes to stderr, the trace to stdout:
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "hi");
+    let trace = String::from_utf8(output.stdout).unwrap();
+    assert!(!trace.contains("hi"));
+    assert!(trace.lines().count() >= 6);
+}
@@ -1,12 +1,14 @@
 use std::path::PathBuf;
 
-use drv_isa_sim::*;
+use drv_isa_sim::{run::HaltReason, *};
 use insta::assert_debug_snapshot;
 
 // Note: Must match input elf file linker script!
 const ROM_START: u32 = 0x1000000;
 const RAM_START: u32 = 0x2000000;
 
+const MAX_STEPS: u64 = 1000;
+
 fn new_simulator(elf_file: PathBuf) -> DRVSim {
     let mut sim = DRVSim::new(DRVSimConfig {
         entry: ROM_START,
@@ -25,8 +27,11 @@ fn new_simulator(elf_file: PathBuf) -> DRVSim {
             },
         ],
         reg_init: ValueInit::Error,
+        fault_mode: FaultMode::Error,
+        zicsr: false,
     });
-    sim.load_elf(elf_file).unwrap();
+    let entry = sim.load_elf(elf_file).unwrap();
+    sim.set_pc(entry);
     sim
 }
 
@@ -34,9 +39,11 @@ fn new_simulator(elf_file: PathBuf) -> DRVSim {
 fn run_01_jumps_and_adds() {
     let mut sim = new_simulator("testdata/01_jumps_and_adds.elf".into());
     let mut log = vec![];
-    for _ in 0..9 {
-        log.push(sim.step().unwrap().to_log_string());
-    }
+    let reason = sim.run_until(MAX_STEPS, |inst| {
+        log.push(inst.to_log_string());
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
     assert_debug_snapshot!(log);
 }
 
@@ -44,9 +51,11 @@ fn run_01_jumps_and_adds() {
 fn run_02_mem_access() {
     let mut sim = new_simulator("testdata/02_mem_access.elf".into());
     let mut log = vec![];
-    for _ in 0..20 {
-        log.push(sim.step().unwrap().to_log_string());
-    }
+    let reason = sim.run_until(MAX_STEPS, |inst| {
+        log.push(inst.to_log_string());
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
     assert_debug_snapshot!(log);
 }
 
@@ -54,9 +63,11 @@ fn run_02_mem_access() {
 fn run_03_branching() {
     let mut sim = new_simulator("testdata/03_branching.elf".into());
     let mut log = vec![];
-    for _ in 0..24 {
-        log.push(sim.step().unwrap().to_log_string());
-    }
+    let reason = sim.run_until(MAX_STEPS, |inst| {
+        log.push(inst.to_log_string());
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
     assert_debug_snapshot!(log);
 }
 
@@ -64,8 +75,30 @@ fn run_03_branching() {
 fn run_04_call_return() {
     let mut sim = new_simulator("testdata/04_call_return.elf".into());
     let mut log = vec![];
-    for _ in 0..15 {
-        log.push(sim.step().unwrap().to_log_string());
-    }
+    let reason = sim.run_until(MAX_STEPS, |inst| {
+        log.push(inst.to_log_string());
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
+    assert_debug_snapshot!(log);
+}
+
+#[test]
+fn run_04_call_return_symbolized() {
+    let mut sim = new_simulator("testdata/04_call_return.elf".into());
+    assert_eq!(sim.symbols().by_name("f1").unwrap().adr, ROM_START + 0x18);
+
+    // Reloading does not duplicate symbols:
+    let count = sim.symbols().iter().count();
+    sim.load_elf("testdata/04_call_return.elf".into()).unwrap();
+    assert_eq!(sim.symbols().iter().count(), count);
+
+    let symbols = sim.symbols().clone();
+    let mut log = vec![];
+    let reason = sim.run_until(MAX_STEPS, |inst| {
+        log.push(inst.to_symbolized_log_string(&symbols));
+        false
+    });
+    assert!(matches!(reason, HaltReason::SelfLoop(_)));
     assert_debug_snapshot!(log);
 }